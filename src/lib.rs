@@ -0,0 +1,17 @@
+//! Eyebeam, a ray tracer. Load a scene from a TOML file, or build one up in code, then hand it
+//! to a [`Renderer`] to get back frames of linear colour:
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use raytracing::{Renderer, structs::{scene_file::load_scene, tonemap::{ToneCurve, ToneMapper}}};
+//!
+//! let mut scene = load_scene(Path::new("scenes/teapot.toml")).unwrap();
+//! let renderer = Renderer::new(800, 450).on_progress(|progress| println!("{:.0}%", progress.fraction() * 100.0));
+//!
+//! let framebuffer = renderer.render_frame(&mut scene);
+//! let pixels = framebuffer.to_rgba(&ToneMapper::new(ToneCurve::Aces, 0.0));
+//! ```
+
+pub mod structs;
+
+pub use structs::{renderer::{Progress, Renderer}, error::{Error, Result}};
@@ -1,22 +1,40 @@
-mod structs;
+use std::{fs, io::{self, Write}, path::{Path, PathBuf}, process};
 
-use std::{path::PathBuf, thread, sync::{Arc, Mutex}};
-
-use argparse::{ArgumentParser, StoreTrue, Store};
+use argparse::{ArgumentParser, StoreTrue, Store, StoreOption};
 use image::Rgba;
 use raster::{Color, Image};
-use structs::{scene::Scene, camera::Camera, vec3::Vec3, light::Light, plane::Plane, appearance::Appearance, finish::Finish, color::color_from_hex, animate::Animate};
-
-use ndarray::Array3;
-use video_rs::{Encoder, EncoderSettings, Locator, Time};
-
-use crate::structs::mesh::ColoredMesh;
+use raytracing::{Error, Renderer, structs::{camera::Projection, scene::Scene, scene_file::load_scene, sampler::{Filter, SamplePattern, Sampler}, tonemap::{ToneCurve, ToneMapper}, gif_output::{GifOptions, GifPalette, GifWriter}, hdr_output::{is_float_format, save_float}, framebuffer::Framebuffer, sequence::{FramePattern, read_frame}, video_output::{parse_bitrate, PixelFormat, RateControl, VideoCodec, VideoOptions, VideoWriter}}};
 
 fn main() {
     let mut video: bool = false;
     let mut duration: f64 = 3.0;
     let mut frame_rate: usize = 60;
+    let mut start_frame: usize = 0;
+    let mut end_frame: Option<usize> = None;
     let mut filename = String::new();
+    let mut scene_file = String::from("scenes/teapot.toml");
+    let mut tone_curve = ToneCurve::Clamp;
+    let mut exposure: f64 = 0.0;
+    let mut width: usize = 1600;
+    let mut height: usize = 900;
+    let mut projection: Option<Projection> = None;
+    let mut samples: usize = 1;
+    let mut pattern: Option<SamplePattern> = None;
+    let mut filter = Filter::Box;
+    let mut seed: u64 = 0;
+    let mut adaptive: Option<f64> = None;
+    let mut max_samples: usize = 64;
+    let mut heatmap = String::new();
+    let mut threads: usize = 0;
+    let mut gif_palette = GifPalette::Global;
+    let mut dither = false;
+    let mut loops: u16 = 0;
+    let mut assemble = String::new();
+    let mut codec = VideoCodec::H264;
+    let mut pixel_format = PixelFormat::Yuv420;
+    let mut crf: Option<u8> = None;
+    let mut bitrate: Option<String> = None;
+    let mut keyframe_interval: Option<usize> = None;
 
     {
         let mut ap = ArgumentParser::new();
@@ -32,121 +50,279 @@ fn main() {
         ap.refer(&mut frame_rate)
           .add_option(&["-f", "--frame-rate"], Store, "Frame rate of the video.");
 
+        ap.refer(&mut start_frame)
+          .add_option(&["--start-frame"], Store, "First frame to render, counting from 0; for a still, the moment of the animation to show.");
+
+        ap.refer(&mut end_frame)
+          .add_option(&["--end-frame"], StoreOption, "Last frame to render (inclusive); defaults to the last frame of --duration.");
+
+        ap.refer(&mut scene_file)
+          .add_option(&["-s", "--scene"], Store, "Scene file (TOML) to render.");
+
         ap.refer(&mut filename)
-          .add_option(&["-o", "--output"], Store, "Filename to store the rendered image/video under.");
+          .add_option(&["-o", "--output"], Store, "Path to write the rendered image/video to (default out/img.png or out/video.mp4); with --video, a name like out/frames/frame_%04d.png saves each frame as a numbered image.");
+
+        ap.refer(&mut assemble)
+          .add_option(&["--assemble"], Store, "Instead of rendering, turns an existing image sequence (e.g. out/frames/frame_%04d.png) into a video at --frame-rate.");
+
+        ap.refer(&mut codec)
+          .add_option(&["--codec"], Store, "Video codec; only h264 (the default) for now.");
+
+        ap.refer(&mut pixel_format)
+          .add_option(&["--pixel-format"], Store, "Video pixel format: yuv420p (default, plays everywhere), yuv422p or yuv444p (sharper colour).");
+
+        ap.refer(&mut crf)
+          .add_option(&["--crf"], StoreOption, "Constant video quality, from 0 (lossless) to 51 (worst); the default is 23.");
+
+        ap.refer(&mut bitrate)
+          .add_option(&["--bitrate"], StoreOption, "Target video bitrate in bits per second, like 800k or 4M, instead of --crf.");
+
+        ap.refer(&mut keyframe_interval)
+          .add_option(&["--keyint"], StoreOption, "Most frames between video keyframes; fewer makes seeking faster and the file bigger.");
+
+        ap.refer(&mut gif_palette)
+          .add_option(&["--gif-palette"], Store, "For .gif videos: one global palette (default) or one per-frame.");
+
+        ap.refer(&mut dither)
+          .add_option(&["--dither"], StoreTrue, "Dithers .gif videos to hide banding in gradients.");
+
+        ap.refer(&mut loops)
+          .add_option(&["--loops"], Store, "How many times a .gif video plays; 0 (the default) loops forever.");
+
+        ap.refer(&mut width)
+          .add_option(&["-W", "--width"], Store, "Width of the output in pixels.");
+
+        ap.refer(&mut height)
+          .add_option(&["-H", "--height"], Store, "Height of the output in pixels.");
+
+        ap.refer(&mut projection)
+          .add_option(&["-p", "--projection"], StoreOption, "Overrides the scene's projection: perspective, orthographic, fisheye, equirectangular (use a 2:1 output) or cubemap (3:2).");
+
+        ap.refer(&mut samples)
+          .add_option(&["-n", "--samples"], Store, "Samples per pixel (default 1).");
+
+        ap.refer(&mut pattern)
+          .add_option(&["--sampling"], StoreOption, "Where samples land within a pixel: jittered (the default for more than one sample) or stratified.");
+
+        ap.refer(&mut filter)
+          .add_option(&["--filter"], Store, "Reconstruction filter: box (default), tent, gaussian or mitchell.");
+
+        ap.refer(&mut seed)
+          .add_option(&["--seed"], Store, "Seed for the random sampling; the same seed gives the same image.");
+
+        ap.refer(&mut adaptive)
+          .add_option(&["-a", "--adaptive"], StoreOption, "Samples adaptively instead: starts from each pixel's corners and splits wherever they differ by more than this (0 to 1).");
+
+        ap.refer(&mut max_samples)
+          .add_option(&["--max-samples"], Store, "Most samples any one pixel can take with --adaptive.");
+
+        ap.refer(&mut heatmap)
+          .add_option(&["--heatmap"], Store, "Also saves an image (at this path) of how many samples each pixel took, from blue (fewest) to red (most).");
+
+        ap.refer(&mut threads)
+          .add_option(&["-j", "--threads"], Store, "Number of render threads; 0 (the default) uses every core.");
+
+        ap.refer(&mut tone_curve)
+          .add_option(&["-t", "--tonemap"], Store, "Tone curve to use: clamp (default), reinhard, filmic or aces.");
+
+        ap.refer(&mut exposure)
+          .add_option(&["-e", "--exposure"], Store, "Exposure adjustment in stops (EV), applied before tone mapping.");
 
         ap.parse_args_or_exit();
     }
 
     if filename.is_empty() {
-        filename = (if video {"video.mp4"} else {"img.png"}).into();
-    }
-
-    let camera: Camera = Camera::new(
-        Vec3::new(-5, -5, -12),
-        Vec3::O,
-        4.0, 9.0 / 4.0
-    );
-    
-    let background = Rgba([0, 0, 0, 255]);
-
-    let mut scene: Scene = Scene::new(camera, background);
-
-    let cyan_appearance = Appearance::new(color_from_hex("#00FFFF").unwrap(), Finish::new(0.0, 0.7, 1.0, 1.0));
-    let red_appearance = Appearance::new(color_from_hex("#FF0000").unwrap(), Finish::new(0.0, 0.7, 0.7, 0.7));
-
-    // shapes
-    scene.shapes.push(Box::new(ColoredMesh::new("res/teapot.obj", Vec3::new(-5, -5, 0), red_appearance)));
-
-    // plane
-    scene.shapes.push(Box::new(Plane::new(Vec3::O + Vec3::J, Vec3::J.invert(), cyan_appearance)));
-
-    // light
-    scene.lights.push(Light::new(Vec3::new(5, -5, -5) * 10, color_from_hex("#FFFFFF").unwrap()));
-
-    
-    if video {
-        // video encoder setup
-        video_rs::init().unwrap();
-        
-        let mut pixel_data = Arc::new(Mutex::new(Array3::<u8>::zeros((900, 1600, 3))));
-        let frame_delta: f64 = 1.0 / frame_rate as f64;
-
-        scene.camera.add_camera_move(Vec3::new(5, -5, -12), 3.0, Some(Vec3::O));
-
-        let destination: Locator = PathBuf::from("out/video.mp4").into();
-        let settings = EncoderSettings::for_h264_yuv420p(1600, 900, false);
-    
-        let mut video_encoder = Encoder::new(&destination, settings).expect("Unable to create encoder");
-    
-        let video_frame_time = Time::from_nth_of_a_second(frame_rate);
-        let mut video_position = Time::zero();
-
-        for frame_num in 0..(duration / frame_delta).ceil() as i32 {
-            println!("Drawing frame {}...", frame_num);
-    
-            thread::scope(|scope| {
-                // shadow these so that they don't need to get moved into the scoped threads
-                let scene = &scene;
-                let pixel_data = &pixel_data;
-    
-                for j in 0..16 {
-                    scope.spawn(move || {
-                        for x in j*100..(j+1)*100 {
-                            for y in 0..900 {
-                                let color = scene.trace((x as f64 / 1600.0) - 0.5, (y as f64 / 900.0) - 0.5);
-    
-                                {
-                                    let mut data = pixel_data.lock().unwrap();
-                                    data[[y, x, 0]] = color.0[0];
-                                    data[[y, x, 1]] = color.0[1];
-                                    data[[y, x, 2]] = color.0[2];
-                                }
-                            }
-                        }
-                    });
-                }
-            });
-    
-            println!("Encoding frame {}...", frame_num);
-            // let frame = Array3::from_shape_fn((900, 1600, 3), |(y, x, c)| pixels[900 * x + y][c]);
-            video_encoder.encode(&pixel_data.lock().unwrap(), &video_position).unwrap_or_else(|_| panic!("Unable to encode {}th frame", frame_num));
-        
-            // update scene and video encoder
-            scene.update(frame_delta);
-            video_position = video_position.aligned_with(&video_frame_time).add();
-        
-            // pixels.clear();
-            pixel_data = Arc::new(Mutex::new(Array3::<u8>::zeros((900, 1600, 3))));
+        filename = (if video || !assemble.is_empty() {"out/video.mp4"} else {"out/img.png"}).into();
+    }
+
+    if width == 0 || height == 0 {
+        eprintln!("Output size must be at least 1x1 (got {}x{})", width, height);
+        process::exit(1);
+    }
+
+    let tonemapper = ToneMapper::new(tone_curve, exposure);
+    let output = PathBuf::from(&filename);
+    let sequence = FramePattern::parse(&filename);
+
+    if frame_rate == 0 {
+        exit_with(Error::Output { path: output, message: "the frame rate must be at least 1".into() });
+    }
+
+    let rate = match (crf, bitrate) {
+        (Some(_), Some(_)) => exit_with(Error::Output { path: output, message: "--crf and --bitrate can't be used together".into() }),
+        (Some(crf), None) => RateControl::Crf(crf),
+        (None, Some(bitrate)) => RateControl::Bitrate(parse_bitrate(&bitrate).unwrap_or_else(|message| exit_with(Error::Output { path: output.clone(), message }))),
+        (None, None) => RateControl::DEFAULT
+    };
+
+    let gif_options = GifOptions { palette: gif_palette, dither, loops, frame_rate };
+    let video_options = VideoOptions { codec, pixel_format, rate, keyframe_interval, frame_rate };
+
+    if !assemble.is_empty() {
+        if sequence.is_some() {
+            exit_with(Error::Sequence { path: filename.into(), message: "Frames can only be assembled into a video, not another sequence".into() });
         }
-    
-        video_encoder.finish().expect("Unable to finish encoding.");
-    } else {
-        let image: Arc<Mutex<Image>> = Arc::new(Mutex::new(Image::blank(1600, 900)));
-
-        thread::scope(|scope| {
-            // shadow these so that they don't need to get moved into the scoped threads
-            let scene = &scene;
-            let image = &image;
-
-            for j in 0..16 {
-                scope.spawn(move || {
-                    for x in j*100..(j+1)*100 {
-                        for y in 0..900 {
-                            let color = scene.trace((x as f64 / 1600.0) - 0.5, (y as f64 / 900.0) - 0.5);
-                            
-                            {
-                                let mut img = image.lock().unwrap();
-                                img.set_pixel(x, y, Color { r: color.0[0], g: color.0[1], b: color.0[2], a: 0xFF }).expect("Unable to set pixel");
-                            }
-                        }
-                    }
-                });
-            }
+
+        assemble_sequence(&assemble, &output, gif_options, video_options, &tonemapper).unwrap_or_else(|err| exit_with(err));
+        println!("\nDone.");
+        return;
+    }
+
+    let last_frame = end_frame.unwrap_or(Renderer::frame_count(duration, frame_rate).saturating_sub(1));
+
+    if video && start_frame > last_frame {
+        exit_with(Error::Output { path: output, message: format!("nothing to render from frame {} to {}", start_frame, last_frame) });
+    }
+
+    let frames = start_frame..last_frame + 1;
+    // a lone sample goes through the pixel's centre unless asked otherwise, keeping edges steady from frame to frame
+    let pattern = pattern.unwrap_or(if samples > 1 { SamplePattern::Jittered } else { SamplePattern::Stratified });
+    let mut sampler = Sampler::new(samples, pattern, filter, seed);
+
+    if let Some(threshold) = adaptive {
+        sampler = sampler.with_adaptive(threshold, max_samples);
+    }
+
+    let renderer = Renderer::new(width, height)
+        .with_sampler(sampler)
+        .with_threads(threads)
+        .on_progress(|progress| {
+            print!("\rRendering... {:3.0}%", progress.fraction() * 100.0);
+            io::stdout().flush().ok();
         });
 
-        raster::save(&image.lock().unwrap(), &format!("out/{}", filename)).expect("Unable to save");
+    let mut scene: Scene = match load_scene(Path::new(&scene_file)) {
+        Ok(scene) => scene,
+        Err(err) => exit_with(err)
+    };
+
+    if let Some(projection) = projection {
+        scene.camera.projection = projection;
+    }
+
+    create_parent(&sequence.as_ref().map_or(output.clone(), |pattern| pattern.path(0))).unwrap_or_else(|err| exit_with(err));
+
+    if let (true, Some(pattern)) = (video, &sequence) {
+        renderer.render_frames(&mut scene, frames, frame_rate, |n, framebuffer| save_still(&framebuffer, &tonemapper, &pattern.path(n)))
+            .unwrap_or_else(|err| exit_with(err));
+    } else if video {
+        let mut writer = VideoOutput::create(&output, width, height, gif_options, video_options).unwrap_or_else(|err| exit_with(err));
+
+        renderer.render_frames(&mut scene, frames, frame_rate, |_, framebuffer| writer.add_frame(&framebuffer.to_rgba(&tonemapper)))
+            .unwrap_or_else(|err| exit_with(err));
+
+        writer.finish().unwrap_or_else(|err| exit_with(err));
+    } else {
+        let framebuffer = renderer.render_at(&mut scene, start_frame as f64 / frame_rate as f64);
+
+        if !heatmap.is_empty() {
+            let path = Path::new(&heatmap);
+            create_parent(path).and_then(|_| save_raster(&framebuffer.heatmap(), width, path)).unwrap_or_else(|err| exit_with(err));
+        }
+
+        save_still(&framebuffer, &tonemapper, &output).unwrap_or_else(|err| exit_with(err));
+    }
+
+    println!("\nDone.")
+}
+
+fn is_gif(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("gif"))
+}
+
+// So that `-o renders/today/img.png` works without making the directories first
+fn create_parent(path: &Path) -> Result<(), Error> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => fs::create_dir_all(parent).map_err(|source| Error::Write { path: parent.to_path_buf(), source }),
+        _ => Ok(())
+    }
+}
+
+/// A video being written out a frame at a time, either through FFmpeg or as a GIF
+enum VideoOutput {
+    Encoded(VideoWriter),
+    Gif(GifWriter)
+}
+
+impl VideoOutput {
+    fn create(path: &Path, width: usize, height: usize, gif: GifOptions, video: VideoOptions) -> Result<Self, Error> {
+        if is_gif(path) {
+            Ok(VideoOutput::Gif(GifWriter::create(path, width, height, gif)?))
+        } else {
+            Ok(VideoOutput::Encoded(VideoWriter::create(path, width, height, video)?))
+        }
+    }
+
+    fn add_frame(&mut self, pixels: &[Rgba<u8>]) -> Result<(), Error> {
+        match self {
+            VideoOutput::Encoded(writer) => writer.add_frame(pixels),
+            VideoOutput::Gif(writer) => writer.add_frame(pixels)
+        }
+    }
+
+    fn finish(self) -> Result<(), Error> {
+        match self {
+            VideoOutput::Encoded(writer) => writer.finish(),
+            VideoOutput::Gif(writer) => writer.finish()
+        }
+    }
+}
+
+// Turns the numbered frames matching `pattern` into a video, sized after the first of them
+fn assemble_sequence(pattern: &str, video_path: &Path, gif: GifOptions, video: VideoOptions, tonemapper: &ToneMapper) -> Result<(), Error> {
+    let frames = FramePattern::parse(pattern)
+        .ok_or_else(|| Error::Sequence { path: pattern.into(), message: "Expected a frame number in the name, like frame_%04d.png".into() })?
+        .existing();
+
+    let Some(first) = frames.first() else {
+        return Err(Error::Sequence { path: pattern.into(), message: "No frames found".into() });
+    };
+
+    let (width, height, _) = read_frame(first, tonemapper)?;
+    create_parent(video_path)?;
+    let mut output = VideoOutput::create(video_path, width, height, gif, video)?;
+
+    for (i, path) in frames.iter().enumerate() {
+        print!("\rAssembling... {:3.0}%", (i + 1) as f64 * 100.0 / frames.len() as f64);
+        io::stdout().flush().ok();
+
+        let (w, h, pixels) = read_frame(path, tonemapper)?;
+
+        if (w, h) != (width, height) {
+            return Err(Error::Sequence { path: path.clone(), message: format!("Frame is {}x{}, but the sequence started out {}x{}", w, h, width, height) });
+        }
+
+        output.add_frame(&pixels)?;
+    }
+
+    output.finish()
+}
+
+// Reports what went wrong and stops, with a failing exit code for scripts to notice
+fn exit_with(err: Error) -> ! {
+    eprintln!("\n{}", err);
+    process::exit(1);
+}
+
+// Floating-point formats get the raw radiance; anything else is tone mapped first
+fn save_still(framebuffer: &Framebuffer, tonemapper: &ToneMapper, path: &Path) -> Result<(), Error> {
+    if is_float_format(path) {
+        save_float(framebuffer, path)
+    } else {
+        // tone mapping happens once the whole image is in, rather than pixel by pixel
+        save_raster(&framebuffer.to_rgba(tonemapper), framebuffer.width, path)
+    }
+}
+
+fn save_raster(pixels: &[Rgba<u8>], width: usize, path: &Path) -> Result<(), Error> {
+    // raster's errors only implement Debug
+    let raster_error = |err| Error::Output { path: path.to_path_buf(), message: format!("{:?}", err) };
+    let mut image = Image::blank(width as i32, (pixels.len() / width) as i32);
+
+    for (i, color) in pixels.iter().enumerate() {
+        let (x, y) = ((i % width) as i32, (i / width) as i32);
+        image.set_pixel(x, y, Color { r: color.0[0], g: color.0[1], b: color.0[2], a: 0xFF }).map_err(raster_error)?;
     }
 
-    println!("Done.")
-}
\ No newline at end of file
+    raster::save(&image, &path.to_string_lossy()).map_err(raster_error)
+}
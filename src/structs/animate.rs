@@ -1,4 +1,6 @@
+/// Anything whose state depends on the time, worked out afresh for each moment rather than
+/// stepped from the last one, so any frame can be rendered without playing through the ones before.
 pub trait Animate {
-    fn start(&mut self);
-    fn update(&mut self, delta: f64);    
+    /// Puts everything where it is `time` seconds into the animation.
+    fn set_time(&mut self, time: f64);
 }
\ No newline at end of file
@@ -0,0 +1,105 @@
+use super::{keyframe::Track, scene::Scene, vec3::Vec3, color::Color, finish::Finish, matrix::Transform};
+
+/// One of the numbers in a `Finish`, for tracks that animate it.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FinishParameter {
+    Ambient,
+    Diffuse,
+    Shiny,
+    Reflect,
+    Transmit,
+    Ior,
+    Absorb
+}
+
+impl FinishParameter {
+    pub fn value_mut<'f>(&self, finish: &'f mut Finish) -> &'f mut f64 {
+        match self {
+            FinishParameter::Ambient => &mut finish.ambient,
+            FinishParameter::Diffuse => &mut finish.diffuse,
+            FinishParameter::Shiny => &mut finish.shiny,
+            FinishParameter::Reflect => &mut finish.reflect,
+            FinishParameter::Transmit => &mut finish.transmit,
+            FinishParameter::Ior => &mut finish.ior,
+            FinishParameter::Absorb => &mut finish.absorb
+        }
+    }
+}
+
+/// An animated transform: scale, then rotation (degrees about x, then y, then z), then
+/// translation, all applied after `base`, the transform the shape had to begin with.
+#[derive(Debug, Clone)]
+pub struct TransformTrack {
+    pub base: Transform,
+    pub scale: Track<Vec3>,
+    pub rotate: Track<Vec3>,
+    pub translate: Track<Vec3>
+}
+
+impl TransformTrack {
+    /// Starts out doing nothing on top of `base`; replace whichever parts should move.
+    pub fn new(base: Transform) -> Self {
+        Self {
+            base,
+            scale: Track::constant(Vec3::new(1, 1, 1)),
+            rotate: Track::constant(Vec3::O),
+            translate: Track::constant(Vec3::O)
+        }
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let value = |track: &Track<Vec3>, default| track.value_at(time).unwrap_or(default);
+        let animated = Transform::compose(value(&self.scale, Vec3::new(1, 1, 1)), value(&self.rotate, Vec3::O), value(&self.translate, Vec3::O));
+
+        self.base.then(&animated)
+    }
+}
+
+/// A track together with what it drives. Shapes and lights are picked out by their index in the scene.
+#[derive(Debug, Clone)]
+pub enum Animation {
+    Background(Track<Color>),
+    LightPosition(usize, Track<Vec3>),
+    LightColor(usize, Track<Color>),
+    // see `Shape::location_mut` for which point this is
+    ShapeLocation(usize, Track<Vec3>),
+    // the shape needs a transform of its own: wrap it in a `Transformed` if it hasn't got one
+    ShapeTransform(usize, Box<TransformTrack>),
+    ShapeColor(usize, Track<Color>),
+    ShapeFinish(usize, FinishParameter, Track<f64>)
+}
+
+impl Animation {
+    /// Sets whatever this animates to its value at `time`. Targets that don't exist, or shapes
+    /// without the property in question, are left alone.
+    pub fn apply(&self, scene: &mut Scene, time: f64) {
+        match self {
+            Animation::Background(track) => set(&mut scene.background, track.value_at(time)),
+            Animation::LightPosition(i, track) => if let Some(light) = scene.lights.get_mut(*i) {
+                set(&mut light.position, track.value_at(time));
+            },
+            Animation::LightColor(i, track) => if let Some(light) = scene.lights.get_mut(*i) {
+                set(&mut light.color, track.value_at(time));
+            },
+            Animation::ShapeLocation(i, track) => if let Some(location) = scene.shapes_mut().get_mut(*i).and_then(|s| s.location_mut()) {
+                set(location, track.value_at(time));
+            },
+            Animation::ShapeTransform(i, track) => if let Some(transform) = scene.shapes_mut().get_mut(*i).and_then(|s| s.transform_mut()) {
+                *transform = track.at(time);
+            },
+            Animation::ShapeColor(i, track) => if let Some(appearance) = scene.shapes_mut().get_mut(*i).and_then(|s| s.appearance_mut()) {
+                set(&mut appearance.material, track.value_at(time));
+            },
+            Animation::ShapeFinish(i, parameter, track) => if let Some(appearance) = scene.shapes_mut().get_mut(*i).and_then(|s| s.appearance_mut()) {
+                set(parameter.value_mut(&mut appearance.finish), track.value_at(time));
+            }
+        }
+    }
+}
+
+// Tracks without any keys leave things as they are
+fn set<T>(target: &mut T, value: Option<T>) {
+    if let Some(value) = value {
+        *target = value;
+    }
+}
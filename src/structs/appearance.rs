@@ -1,40 +1,94 @@
-use image::Rgba;
-
-use super::{finish::Finish, vec3::Vec3, color::color_scale, scene::Scene, ray::Ray};
+use super::{finish::Finish, color::Color, scene::Scene, ray::Ray, hit::Hit, vec3::Vec3};
 
 #[derive(Clone)]
 pub struct Appearance {
-    pub material: Rgba<u8>,
+    pub material: Color,
     pub finish: Finish
 }
 
 impl Appearance {
-    pub fn new(material: Rgba<u8>, finish: Finish) -> Self {
+    pub fn new(material: Color, finish: Finish) -> Self {
         Self {
             material,
             finish
         }
     }
 
-    pub fn color_at(&self, _point: &Vec3) -> Rgba<u8> {
+    // Solid colour for now; `hit.uv` is where a texture lookup would go
+    pub fn color_at(&self, _hit: &Hit) -> Color {
         self.material
     }
 
-    pub fn ambient_color_at(&self, point: &Vec3) -> Rgba<u8> {
-        color_scale(&self.color_at(point), self.finish.ambient)
+    // whatever light passes through a surface isn't there to light the surface itself
+    pub fn ambient_color_at(&self, hit: &Hit) -> Color {
+        self.color_at(hit) * (self.finish.ambient * (1.0 - self.finish.transmit))
     }
 
-    pub fn diffuse_color_at(&self, point: &Vec3) -> Rgba<u8> {
-        color_scale(&self.color_at(point), self.finish.diffuse)
+    pub fn diffuse_color_at(&self, hit: &Hit) -> Color {
+        self.color_at(hit) * (self.finish.diffuse * (1.0 - self.finish.transmit))
     }
 
-    pub fn reflect(&self, point: &Vec3, reflex: &Vec3, scene: &Scene, depth: i32) ->  Rgba<u8> {
-        if self.finish.reflect <= 0.0 { 
-            Rgba([0, 0, 0, 255])
+    pub fn reflect(&self, hit: &Hit, reflex: &Vec3, scene: &Scene, depth: i32) -> Color {
+        if self.finish.reflect <= 0.0 {
+            Color::BLACK
         } else {
-            let reflected_ray = Ray::new(*point, *reflex);
+            let reflected_ray = Ray::new(hit.point, *reflex);
             let reflected_color = reflected_ray.trace(scene, depth);
-            color_scale(&reflected_color, self.finish.reflect)
+            reflected_color * self.finish.reflect
+        }
+    }
+
+    /// Light coming through a transparent surface, along with the part of it that the Fresnel term reflects instead.
+    pub fn transmit(&self, hit: &Hit, ray: &Ray, reflex: &Vec3, scene: &Scene, depth: i32) -> Color {
+        let finish = &self.finish;
+
+        if finish.transmit <= 0.0 {
+            return Color::BLACK;
+        }
+
+        let normal = hit.facing_normal();
+
+        // Going in through a front face means going from air into this shape, and the other way round on the way out
+        let eta = if hit.front_face { 1.0 / finish.ior } else { finish.ior };
+
+        match ray.refract(&normal, eta) {
+            // total internal reflection: everything that would have passed through bounces back instead
+            None => Ray::new(hit.point, *reflex).trace(scene, depth) * finish.transmit,
+            Some(refracted) => {
+                let cos = if eta > 1.0 { -refracted.dot(&normal) } else { -ray.direction.dot(&normal) };
+                let reflectance = finish.fresnel(cos);
+
+                let through = Ray::new(hit.point, refracted).trace(scene, depth) * (finish.transmit * (1.0 - reflectance));
+                let back = Ray::new(hit.point, *reflex).trace(scene, depth) * (finish.transmit * reflectance);
+
+                through + back
+            }
         }
     }
-}
\ No newline at end of file
+
+    /// Beer's law. A ray leaving through a back face has spent its whole length inside the shape,
+    /// and whatever it brings back is tinted by the distance travelled.
+    pub fn absorb(&self, hit: &Hit, color: &Color) -> Color {
+        if self.finish.absorb <= 0.0 || hit.front_face {
+            return *color;
+        }
+
+        *color * self.attenuation(hit, hit.distance)
+    }
+
+    /// How much of a shadow ray's light gets through this surface: `transmit` of it, and on the way out of
+    /// the shape, tinted by Beer's law for the `inside` units it travelled within it.
+    pub fn transmittance(&self, hit: &Hit, inside: f64) -> Color {
+        let through = Color::WHITE * self.finish.transmit;
+
+        if self.finish.absorb <= 0.0 || hit.front_face {
+            return through;
+        }
+
+        through * self.attenuation(hit, inside)
+    }
+
+    fn attenuation(&self, hit: &Hit, distance: f64) -> Color {
+        self.color_at(hit).map(|channel| (-self.finish.absorb * (1.0 - channel).max(0.0) * distance).exp())
+    }
+}
@@ -0,0 +1,418 @@
+use super::{vec3::Vec3, ray::Ray, util::{fmin, fmax}};
+
+#[derive(Debug, Copy, Clone)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3
+}
+
+impl Aabb {
+    // inside-out box, so that growing it by anything gives back that thing's bounds
+    pub const EMPTY: Aabb = Aabb {
+        min: Vec3 {x: f64::INFINITY, y: f64::INFINITY, z: f64::INFINITY},
+        max: Vec3 {x: f64::NEG_INFINITY, y: f64::NEG_INFINITY, z: f64::NEG_INFINITY}
+    };
+
+    pub fn new(corner_a: Vec3, corner_b: Vec3) -> Self {
+        Aabb::EMPTY.grow(&corner_a).grow(&corner_b)
+    }
+
+    pub fn from_points(points: &[Vec3]) -> Self {
+        points.iter().fold(Aabb::EMPTY, |acc, point| acc.grow(point))
+    }
+
+    pub fn grow(&self, point: &Vec3) -> Self {
+        Aabb {
+            min: Vec3::new(fmin(self.min.x, point.x), fmin(self.min.y, point.y), fmin(self.min.z, point.z)),
+            max: Vec3::new(fmax(self.max.x, point.x), fmax(self.max.y, point.y), fmax(self.max.z, point.z))
+        }
+    }
+
+    pub fn union(&self, other: &Aabb) -> Self {
+        self.grow(&other.min).grow(&other.max)
+    }
+
+    // only meaningful when the boxes overlap; otherwise min ends up past max
+    pub fn intersection(&self, other: &Aabb) -> Self {
+        Aabb {
+            min: Vec3::new(fmax(self.min.x, other.min.x), fmax(self.min.y, other.min.y), fmax(self.min.z, other.min.z)),
+            max: Vec3::new(fmin(self.max.x, other.max.x), fmin(self.max.y, other.max.y), fmin(self.max.z, other.max.z))
+        }
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let e = self.extent();
+
+        if e.x < 0.0 || e.y < 0.0 || e.z < 0.0 {
+            0.0
+        } else {
+            2.0 * (e.x * e.y + e.y * e.z + e.z * e.x)
+        }
+    }
+
+    /// Slab test. Returns the distance at which the ray enters the box, if it does so before `t_max`.
+    /// `inv_dir` is the componentwise reciprocal of the ray direction, hoisted out so it's only computed once per traversal.
+    pub fn hit(&self, ray: &Ray, inv_dir: &Vec3, t_max: f64) -> Option<f64> {
+        let mut t_near = f64::NEG_INFINITY;
+        let mut t_far = t_max;
+
+        for axis in 0..3 {
+            // a ray parallel to the slab is either between its planes all the way or never; the distances
+            // below would be 0 * inf = NaN for one lying in a plane, and sort it out of the box
+            if inv_dir[axis].is_infinite() {
+                if ray.origin[axis] < self.min[axis] || ray.origin[axis] > self.max[axis] { return None; }
+                continue;
+            }
+
+            let t0 = (self.min[axis] - ray.origin[axis]) * inv_dir[axis];
+            let t1 = (self.max[axis] - ray.origin[axis]) * inv_dir[axis];
+            let (t0, t1) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+
+            if t0 > t_near { t_near = t0; }
+            if t1 < t_far { t_far = t1; }
+
+            if t_near > t_far { return None; }
+        }
+
+        if t_far < 0.0 { None } else { Some(t_near) }
+    }
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf { bounds: Aabb, start: usize, count: usize },
+    Branch { bounds: Aabb, left: usize, right: usize }
+}
+
+impl Node {
+    fn bounds(&self) -> &Aabb {
+        match self {
+            Node::Leaf { bounds, .. } | Node::Branch { bounds, .. } => bounds
+        }
+    }
+}
+
+/// Bounding volume hierarchy over anything that can be boxed. It only knows about primitive *indices*;
+/// callers keep their own primitives and decide what a hit on one of them means.
+#[derive(Debug)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    order: Vec<usize>
+}
+
+impl Bvh {
+    const MAX_LEAF_SIZE: usize = 4;
+    const BINS: usize = 16;
+    // relative cost of visiting a node vs. testing one primitive, for the SAH
+    const TRAVERSAL_COST: f64 = 0.5;
+
+    /// Builds the hierarchy with the surface area heuristic, evaluated over `BINS` buckets per axis.
+    pub fn build(bounds: &[Aabb]) -> Self {
+        let centroids: Vec<Vec3> = bounds.iter().map(|b| b.centroid()).collect();
+        let mut bvh = Bvh { nodes: Vec::new(), order: (0..bounds.len()).collect() };
+
+        if !bounds.is_empty() {
+            bvh.build_node(bounds, &centroids, 0, bounds.len());
+        }
+
+        bvh
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::EMPTY, |node| *node.bounds())
+    }
+
+    fn build_node(&mut self, bounds: &[Aabb], centroids: &[Vec3], start: usize, end: usize) -> usize {
+        let node_bounds = self.order[start..end].iter().fold(Aabb::EMPTY, |acc, &i| acc.union(&bounds[i]));
+        let count = end - start;
+        let index = self.nodes.len();
+
+        self.nodes.push(Node::Leaf { bounds: node_bounds, start, count });
+
+        if count <= 1 {
+            return index;
+        }
+
+        let Some(mid) = self.split(bounds, centroids, &node_bounds, start, end) else {
+            return index;
+        };
+
+        let left = self.build_node(bounds, centroids, start, mid);
+        let right = self.build_node(bounds, centroids, mid, end);
+        self.nodes[index] = Node::Branch { bounds: node_bounds, left, right };
+
+        index
+    }
+
+    // Partitions order[start..end] along the cheapest SAH plane and returns the partition point,
+    // or None if this range is better off as a leaf.
+    fn split(&mut self, bounds: &[Aabb], centroids: &[Vec3], node_bounds: &Aabb, start: usize, end: usize) -> Option<usize> {
+        let count = end - start;
+        let centroid_bounds = Aabb::from_points(&self.order[start..end].iter().map(|&i| centroids[i]).collect::<Vec<_>>());
+        let extent = centroid_bounds.extent();
+
+        let mut best: Option<(f64, usize, usize)> = None; // (cost, axis, bin)
+
+        for axis in 0..3 {
+            if extent[axis] <= f64::EPSILON { continue; }
+
+            let bin_of = |i: usize| Self::bin(centroids[i][axis], centroid_bounds.min[axis], extent[axis]);
+
+            let mut bin_bounds = [Aabb::EMPTY; Self::BINS];
+            let mut bin_counts = [0usize; Self::BINS];
+
+            for &i in &self.order[start..end] {
+                let bin = bin_of(i);
+                bin_bounds[bin] = bin_bounds[bin].union(&bounds[i]);
+                bin_counts[bin] += 1;
+            }
+
+            // sweep from the right so each split's right-hand side is available in O(1)
+            let mut right_area = [0.0; Self::BINS];
+            let mut right_count = [0usize; Self::BINS];
+            let mut acc_bounds = Aabb::EMPTY;
+            let mut acc_count = 0;
+
+            for bin in (1..Self::BINS).rev() {
+                acc_bounds = acc_bounds.union(&bin_bounds[bin]);
+                acc_count += bin_counts[bin];
+                right_area[bin] = acc_bounds.surface_area();
+                right_count[bin] = acc_count;
+            }
+
+            acc_bounds = Aabb::EMPTY;
+            acc_count = 0;
+
+            for bin in 1..Self::BINS {
+                acc_bounds = acc_bounds.union(&bin_bounds[bin - 1]);
+                acc_count += bin_counts[bin - 1];
+
+                if acc_count == 0 || right_count[bin] == 0 { continue; }
+
+                let cost = acc_bounds.surface_area() * acc_count as f64 + right_area[bin] * right_count[bin] as f64;
+
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, bin));
+                }
+            }
+        }
+
+        let (cost, axis, bin) = best?;
+
+        let leaf_cost = count as f64;
+        let split_cost = Self::TRAVERSAL_COST + cost / node_bounds.surface_area().max(f64::EPSILON);
+
+        if count <= Self::MAX_LEAF_SIZE && split_cost >= leaf_cost {
+            return None;
+        }
+
+        // in-place partition of everything left of the chosen bin boundary
+        let mut mid = start;
+        for i in start..end {
+            if Self::bin(centroids[self.order[i]][axis], centroid_bounds.min[axis], extent[axis]) < bin {
+                self.order.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        if mid == start || mid == end { None } else { Some(mid) }
+    }
+
+    fn bin(centroid: f64, min: f64, extent: f64) -> usize {
+        (((centroid - min) / extent) * Self::BINS as f64).min(Self::BINS as f64 - 1.0) as usize
+    }
+
+    /// Calls `f` with every primitive whose leaf box the ray passes through.
+    /// Used when every hit along the ray is wanted, not just the nearest one.
+    pub fn visit<F>(&self, ray: &Ray, mut f: F)
+        where F: FnMut(usize)
+    {
+        if self.nodes.is_empty() { return; }
+
+        let inv_dir = Self::inverse(&ray.direction);
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds().hit(ray, &inv_dir, f64::INFINITY).is_none() { continue; }
+
+            match *node {
+                Node::Leaf { start, count, .. } => self.order[start..start + count].iter().for_each(|&i| f(i)),
+                Node::Branch { left, right, .. } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+    }
+
+    /// Finds the closest primitive along the ray. `f` intersects a single primitive and returns
+    /// the hit distance, if any; boxes further away than the best hit so far are skipped.
+    pub fn nearest<F>(&self, ray: &Ray, mut f: F) -> Option<(usize, f64)>
+        where F: FnMut(usize) -> Option<f64>
+    {
+        if self.nodes.is_empty() { return None; }
+
+        let inv_dir = Self::inverse(&ray.direction);
+        let mut best: Option<(usize, f64)> = None;
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let t_max = best.map_or(f64::INFINITY, |(_, t)| t);
+            let node = &self.nodes[index];
+
+            if node.bounds().hit(ray, &inv_dir, t_max).is_none() { continue; }
+
+            match *node {
+                Node::Leaf { start, count, .. } => {
+                    for &i in &self.order[start..start + count] {
+                        if let Some(t) = f(i) {
+                            if t < best.map_or(f64::INFINITY, |(_, best_t)| best_t) {
+                                best = Some((i, t));
+                            }
+                        }
+                    }
+                },
+                Node::Branch { left, right, .. } => {
+                    // push the farther child first, so the nearer one is popped (and can shrink t_max) first
+                    let t_left = self.nodes[left].bounds().hit(ray, &inv_dir, t_max);
+                    let t_right = self.nodes[right].bounds().hit(ray, &inv_dir, t_max);
+
+                    match (t_left, t_right) {
+                        (Some(l), Some(r)) if l < r => { stack.push(right); stack.push(left); },
+                        (Some(_), Some(_)) => { stack.push(left); stack.push(right); },
+                        (Some(_), None) => stack.push(left),
+                        (None, Some(_)) => stack.push(right),
+                        (None, None) => ()
+                    }
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Whether `f` reports a hit on any primitive closer than `t_max`. Stops at the first one found,
+    /// which is all a shadow ray needs to know.
+    pub fn any<F>(&self, ray: &Ray, t_max: f64, mut f: F) -> bool
+        where F: FnMut(usize) -> bool
+    {
+        if self.nodes.is_empty() { return false; }
+
+        let inv_dir = Self::inverse(&ray.direction);
+        let mut stack = vec![0];
+
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+
+            if node.bounds().hit(ray, &inv_dir, t_max).is_none() { continue; }
+
+            match *node {
+                Node::Leaf { start, count, .. } => {
+                    if self.order[start..start + count].iter().any(|&i| f(i)) {
+                        return true;
+                    }
+                },
+                Node::Branch { left, right, .. } => {
+                    stack.push(left);
+                    stack.push(right);
+                }
+            }
+        }
+
+        false
+    }
+
+    fn inverse(direction: &Vec3) -> Vec3 {
+        Vec3::new(1.0 / direction.x, 1.0 / direction.y, 1.0 / direction.z)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{bvh::{Aabb, Bvh}, vec3::Vec3, ray::Ray};
+
+    // a 10x10x10 grid of unit boxes, spaced out so that there are gaps between them
+    fn grid() -> Vec<Aabb> {
+        let mut boxes = Vec::new();
+
+        for x in 0..10 {
+            for y in 0..10 {
+                for z in 0..10 {
+                    let corner = Vec3::new(x, y, z) * 2;
+                    boxes.push(Aabb::new(corner, corner + Vec3::new(1, 1, 1)));
+                }
+            }
+        }
+
+        boxes
+    }
+
+    #[test]
+    fn nearest_matches_brute_force() {
+        let boxes = grid();
+        let bvh = Bvh::build(&boxes);
+
+        let rays = [
+            Ray::new(Vec3::new(-5.0, 0.5, 0.5), Vec3::I),
+            Ray::new(Vec3::new(30.0, 30.0, 30.0), Vec3::new(-1, -1, -1)),
+            Ray::new(Vec3::new(4.5, -10.0, 6.5), Vec3::new(0.1, 1.0, 0.05)),
+            Ray::new(Vec3::new(-1.0, -1.0, -1.0), Vec3::new(-1, 0, 0))
+        ];
+
+        for ray in rays {
+            let intersect = |i: usize| {
+                let inv = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+                boxes[i].hit(&ray, &inv, f64::INFINITY).filter(|&t| t >= 0.0)
+            };
+
+            let expected = (0..boxes.len()).filter_map(|i| intersect(i).map(|t| (i, t))).reduce(|a, b| if b.1 < a.1 {b} else {a});
+            let actual = bvh.nearest(&ray, intersect);
+
+            assert_eq!(expected.map(|(_, t)| t), actual.map(|(_, t)| t), "{:?}", ray);
+        }
+    }
+
+    #[test]
+    fn grazing_rays_hit_faces() {
+        let unit = Aabb::new(Vec3::O, Vec3::new(1, 1, 1));
+        let hit = |origin: Vec3, direction: Vec3| {
+            let ray = Ray::new(origin, direction);
+            let inv = Vec3::new(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+            unit.hit(&ray, &inv, f64::INFINITY)
+        };
+
+        // lying in the min and max faces, then in an edge, and from inside the face itself
+        assert_eq!(hit(Vec3::new(-1.0, 0.0, 0.5), Vec3::I), Some(1.0));
+        assert_eq!(hit(Vec3::new(-1.0, 1.0, 0.5), Vec3::I), Some(1.0));
+        assert_eq!(hit(Vec3::new(0.5, -2.0, 0.0), Vec3::J), Some(2.0));
+        assert_eq!(hit(Vec3::new(-1.0, 0.0, 0.0), Vec3::I), Some(1.0));
+        assert_eq!(hit(Vec3::new(0.5, 0.0, 0.5), Vec3::K), Some(-0.5));
+
+        // and just outside a face is still a miss
+        assert_eq!(hit(Vec3::new(-1.0, -1e-9, 0.5), Vec3::I), None);
+    }
+
+    #[test]
+    fn visit_sees_every_box_along_ray() {
+        let boxes = grid();
+        let bvh = Bvh::build(&boxes);
+
+        let ray = Ray::new(Vec3::new(-5.0, 0.5, 0.5), Vec3::I);
+        let mut seen = Vec::new();
+        bvh.visit(&ray, |i| seen.push(i));
+
+        let row: Vec<usize> = (0..boxes.len()).filter(|&i| boxes[i].min.y == 0.0 && boxes[i].min.z == 0.0).collect();
+        assert_eq!(row.len(), 10);
+        assert!(row.iter().all(|i| seen.contains(i)));
+    }
+}
@@ -1,82 +1,551 @@
-use image::Rgba;
+use std::f64::consts::PI;
+use std::str::FromStr;
 
+use rand::Rng;
+
+use super::color::Color;
 use super::animate::Animate;
 use super::vec3::Vec3;
 use super::ray::Ray;
 
 use super::scene::Scene;
 
+/// Field of view in degrees, across whichever direction is held fixed when the aspect ratio changes.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum FieldOfView {
+    Horizontal(f64),
+    Vertical(f64)
+}
+
+/// How film coordinates turn into ray directions.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Projection {
+    // pinhole camera
+    Perspective,
+    // parallel rays from a film as big as the perspective view is at `look_at`, so switching keeps the framing
+    Orthographic,
+    // equidistant fisheye: angle from the centre grows linearly with distance, out to half the field of view at the edge
+    Fisheye,
+    // the whole sphere, longitude across and latitude down, ignoring the field of view
+    Equirectangular,
+    // all six 90 degree faces in a 3x2 grid: right, left, up on top; down, front, back below
+    Cubemap
+}
+
+impl FromStr for Projection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "perspective" => Ok(Projection::Perspective),
+            "orthographic" | "ortho" => Ok(Projection::Orthographic),
+            "fisheye" => Ok(Projection::Fisheye),
+            "equirectangular" | "panorama" => Ok(Projection::Equirectangular),
+            "cubemap" => Ok(Projection::Cubemap),
+            other => Err(format!("Unknown projection `{}` (expected perspective, orthographic, fisheye, equirectangular or cubemap)", other))
+        }
+    }
+}
+
+/// One leg of the camera's path: a steady, straight move to `to` taking `duration` seconds.
+/// With `look_at`, the camera turns to face it along the way; otherwise it keeps its heading.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct CameraMove {
+    pub to: Vec3,
+    pub duration: f64,
+    pub look_at: Option<Vec3>
+}
+
 pub struct Camera {
     pub location: Vec3,
     pub look_at: Vec3,
+    // world direction that should point to the top of the picture; the scene is y-down, so this is usually -J
+    pub up: Vec3,
+    // degrees about the view direction, positive turning the camera counter-clockwise
+    pub roll: f64,
+    pub fov: FieldOfView,
+    pub projection: Projection,
     pub direction: Vec3,
+    // half the film's width and height, as seen from one unit in front of the camera
     pub camera_right: Vec3,
     pub camera_up: Vec3,
-    pub camera_moves: Vec<(Vec3, f64, Option<Vec3>)>,
-    width: f64,
-    height: f64
+    pub camera_moves: Vec<CameraMove>,
+    // where the camera was, and what it was looking at, when the first move was added
+    path_start: (Vec3, Vec3),
+    // radius of the lens in scene units; 0 is a pinhole, with everything in focus
+    pub aperture: f64,
+    // when set, the aperture follows this f-number and the focal length instead
+    pub f_stop: Option<f64>,
+    // distance along the view direction that's in sharp focus; None focuses on `look_at`
+    pub focus_distance: Option<f64>,
+    // the fewest samples per pixel once the lens is open, so that each pixel sees enough of it
+    pub lens_samples: usize,
+    aspect: f64
 }
 
 impl Camera {
-    pub fn new<T>(location: Vec3, look_at: Vec3, width: T, height: T) -> Self 
-        where T: Into<f64> + Copy {
-        let direction = Vec3::between(&location, &look_at).unit();
-        let camera_right = Vec3::J.cross(&direction).unit() * (width.into() / 2.0);
-        let camera_up = camera_right.cross(&direction).unit().invert() * (-height.into() / 2.0);
-        
-        Camera {
+    pub const DEFAULT_LENS_SAMPLES: usize = 32;
+
+    // a full-frame sensor, for working out focal lengths when scene units are taken as metres
+    const SENSOR_WIDTH: f64 = 0.036;
+
+    pub fn new(location: Vec3, look_at: Vec3, fov: FieldOfView) -> Self {
+        let mut camera = Camera {
             location,
             look_at,
-            direction,
-            camera_right,
-            camera_up,
+            up: Vec3::J.invert(),
+            roll: 0.0,
+            fov,
+            projection: Projection::Perspective,
+            direction: Vec3::K,
+            camera_right: Vec3::I,
+            camera_up: Vec3::J.invert(),
             camera_moves: Vec::new(),
-            width: width.into(),
-            height: height.into()
+            path_start: (location, look_at),
+            aperture: 0.0,
+            f_stop: None,
+            focus_distance: None,
+            lens_samples: Camera::DEFAULT_LENS_SAMPLES,
+            aspect: 16.0 / 9.0
+        };
+
+        camera.orient();
+        camera
+    }
+
+    /// A camera given by the size of its film two units in front of it, the way scenes used to be set up.
+    pub fn from_film<T>(location: Vec3, look_at: Vec3, width: T, height: T) -> Self
+        where T: Into<f64> + Copy
+    {
+        let fov = FieldOfView::Horizontal(2.0 * (width.into() / 4.0).atan().to_degrees());
+        let mut camera = Camera::new(location, look_at, fov);
+
+        camera.set_aspect(width.into() / height.into());
+        camera
+    }
+
+    pub fn with_up(mut self, up: Vec3) -> Self {
+        self.up = up;
+        self.orient();
+        self
+    }
+
+    pub fn with_roll(mut self, degrees: f64) -> Self {
+        self.roll = degrees;
+        self.orient();
+        self
+    }
+
+    pub fn with_projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    /// Opens the lens to the given radius, in scene units.
+    pub fn with_aperture(mut self, radius: f64) -> Self {
+        self.aperture = radius;
+        self.f_stop = None;
+        self
+    }
+
+    /// Sizes the lens like a photographic f-number, taking scene units as metres on a full-frame sensor.
+    /// The aperture then keeps following the focal length as the field of view changes.
+    pub fn with_f_stop(mut self, f_stop: f64) -> Self {
+        self.f_stop = Some(f_stop);
+        self.orient();
+        self
+    }
+
+    pub fn with_focus_distance(mut self, distance: f64) -> Self {
+        self.focus_distance = Some(distance);
+        self
+    }
+
+    /// Focuses on the plane through `point`, square to the view direction.
+    pub fn focus_on(mut self, point: &Vec3) -> Self {
+        self.focus_distance = Some(Vec3::between(&self.location, point).dot(&self.direction));
+        self
+    }
+
+    pub fn with_lens_samples(mut self, samples: usize) -> Self {
+        self.lens_samples = samples.max(1);
+        self
+    }
+
+    /// How far away things are sharpest. Unless told otherwise, that's `look_at`, and it stays that way as the camera moves.
+    pub fn focus_distance(&self) -> f64 {
+        self.focus_distance.unwrap_or_else(|| Vec3::between(&self.location, &self.look_at).length())
+    }
+
+    /// The focal length, in scene units, of a lens with this field of view on a full-frame sensor.
+    pub fn focal_length(&self) -> f64 {
+        Camera::SENSOR_WIDTH / 2.0 / self.camera_right.length()
+    }
+
+    /// Sets the film's width over height, normally to match the output. Whichever direction
+    /// the field of view was given for stays put, and the other one follows.
+    pub fn set_aspect(&mut self, aspect: f64) {
+        self.aspect = aspect;
+        self.orient();
+    }
+
+    // Rebuilds the view basis after anything it depends on has changed
+    fn orient(&mut self) {
+        self.direction = Vec3::between(&self.location, &self.look_at).unit();
+
+        let mut right = self.direction.cross(&self.up);
+
+        if right.length() < 1e-9 {
+            // Looking straight along the up axis leaves the picture's rotation undefined.
+            // Fall back to treating whichever world axis is furthest from the view direction as up.
+            let hint = [Vec3::K, Vec3::I, Vec3::J].into_iter()
+                .min_by(|a, b| a.dot(&self.direction).abs().total_cmp(&b.dot(&self.direction).abs()))
+                .unwrap();
+
+            right = self.direction.cross(&hint);
+        }
+
+        let right = right.unit();
+        let up = right.cross(&self.direction).unit();
+
+        let (sin, cos) = self.roll.to_radians().sin_cos();
+        let (right, up) = (right * cos + up * sin, up * cos - right * sin);
+
+        let (half_width, half_height) = match self.fov {
+            FieldOfView::Horizontal(degrees) => {
+                let half_width = (degrees.to_radians() / 2.0).tan();
+                (half_width, half_width / self.aspect)
+            },
+            FieldOfView::Vertical(degrees) => {
+                let half_height = (degrees.to_radians() / 2.0).tan();
+                (half_height * self.aspect, half_height)
+            }
+        };
+
+        self.camera_right = right * half_width;
+        self.camera_up = up * half_height;
+
+        if let Some(f_stop) = self.f_stop {
+            self.aperture = self.focal_length() / f_stop / 2.0;
         }
     }
 
-    pub fn trace<T>(&self, scene: &Scene, x: T, y: T) -> Rgba<u8>
-        where T: Into<f64> + Copy {
+    /// The primary ray through film coordinates (x, y), each running from -0.5 to 0.5 with y pointing down the picture.
+    /// None where the projection doesn't cover the picture (outside a fisheye's circle).
+    pub fn ray<T>(&self, x: T, y: T) -> Option<Ray>
+        where T: Into<f64> + Copy
+    {
+        let (x, y) = (x.into(), y.into());
+        let (right, up, forward) = (self.camera_right.unit(), self.camera_up.unit(), self.direction);
+
+        match self.projection {
+            Projection::Perspective => {
+                let ray_x = self.camera_right * (2.0 * x);
+                let ray_y = self.camera_up.invert() * (2.0 * y);
+
+                Some(Ray::new(self.location, self.direction + ray_x + ray_y))
+            },
+            Projection::Orthographic => {
+                let distance = Vec3::between(&self.location, &self.look_at).length();
+                let offset = (self.camera_right * (2.0 * x) - self.camera_up * (2.0 * y)) * distance;
+
+                Some(Ray::new(self.location + offset, self.direction))
+            },
+            Projection::Fisheye => {
+                // measured in the same units both ways, so the image circle comes out round
+                let (across, down, half_angle) = match self.fov {
+                    FieldOfView::Horizontal(degrees) => (x, y / self.aspect, degrees.to_radians() / 2.0),
+                    FieldOfView::Vertical(degrees) => (x * self.aspect, y, degrees.to_radians() / 2.0)
+                };
+
+                let r = (across * across + down * down).sqrt();
+                let theta = 2.0 * r * half_angle;
 
-        let ray_x = self.camera_right * x.into();
-        let ray_y = self.camera_up.invert() * y.into();
-        let ray_dir = self.direction + ray_x + ray_y;
-        let ray = Ray::new(self.location, ray_dir);
+                if theta > half_angle {
+                    return None;
+                }
 
-        ray.trace(scene, 0)
+                let phi = down.atan2(across);
+                let sideways = right * phi.cos() - up * phi.sin();
+
+                Some(Ray::new(self.location, forward * theta.cos() + sideways * theta.sin()))
+            },
+            Projection::Equirectangular => {
+                let longitude = 2.0 * PI * x;
+                let latitude = -PI * y;
+
+                let dir = (forward * longitude.cos() + right * longitude.sin()) * latitude.cos() + up * latitude.sin();
+                Some(Ray::new(self.location, dir))
+            },
+            Projection::Cubemap => {
+                let (col, row) = (((x + 0.5) * 3.0).floor().clamp(0.0, 2.0), ((y + 0.5) * 2.0).floor().clamp(0.0, 1.0));
+                let u = ((x + 0.5) * 3.0 - col) * 2.0 - 1.0;
+                let v = ((y + 0.5) * 2.0 - row) * 2.0 - 1.0;
+
+                // (looking towards, right of picture, top of picture) for each face
+                let (face, face_right, face_up) = match (row as usize, col as usize) {
+                    (0, 0) => (right, forward.invert(), up),
+                    (0, 1) => (right.invert(), forward, up),
+                    (0, _) => (up, right, forward.invert()),
+                    (_, 0) => (up.invert(), right, forward),
+                    (_, 1) => (forward, right, up),
+                    (_, _) => (forward.invert(), right.invert(), up)
+                };
+
+                Some(Ray::new(self.location, face + face_right * u - face_up * v))
+            }
+        }
     }
 
+    /// Like `ray`, but leaving from the point (u, v) on the lens, each running from 0 to 1 across a square
+    /// that gets mapped onto the lens's disc. Every ray through the same film point crosses at the focus distance.
+    pub fn lens_ray<T>(&self, x: T, y: T, u: f64, v: f64) -> Option<Ray>
+        where T: Into<f64> + Copy
+    {
+        let pinhole = self.ray(x, y)?;
+
+        // parallel rays have nowhere to converge, so an orthographic camera stays sharp
+        if self.aperture <= 0.0 || self.projection == Projection::Orthographic {
+            return Some(pinhole);
+        }
+
+        let focus = match self.projection {
+            // stretched out to the focal plane, square to the view direction
+            Projection::Perspective => pinhole.direction * (self.focus_distance() / pinhole.direction.dot(&self.direction)),
+            // the wide projections look sideways and behind, so focus on a sphere around the camera instead
+            _ => pinhole.direction * self.focus_distance()
+        };
+
+        let (lens_x, lens_y) = concentric_disc(u, v);
+        let offset = self.camera_right.unit() * (lens_x * self.aperture) + self.camera_up.unit() * (lens_y * self.aperture);
+
+        Some(Ray::new(pinhole.origin + offset, focus - offset))
+    }
+
+    /// Traces one ray through (x, y), from a random point on the lens when it's open.
+    pub fn trace<T, R>(&self, scene: &Scene, x: T, y: T, rng: &mut R) -> Color
+        where T: Into<f64> + Copy, R: Rng
+    {
+        let ray = if self.aperture > 0.0 {
+            self.lens_ray(x, y, rng.gen(), rng.gen())
+        } else {
+            self.ray(x, y)
+        };
+
+        match ray {
+            Some(ray) => ray.trace(scene, 0),
+            // past the edge of a fisheye's circle, as if looking at nothing
+            None => scene.background
+        }
+    }
+
+    /// Queues up a move after the ones already added. The path starts from wherever the camera is when the first move goes in.
     pub fn add_camera_move(&mut self, move_to: Vec3, duration: f64, look_at: Option<Vec3>) {
-        self.camera_moves.push((move_to, duration, look_at));
+        if self.camera_moves.is_empty() {
+            self.path_start = (self.location, self.look_at);
+        }
+
+        self.camera_moves.push(CameraMove { to: move_to, duration, look_at });
+    }
+
+    /// How long the camera's moves take altogether, in seconds.
+    pub fn path_duration(&self) -> f64 {
+        self.camera_moves.iter().map(|m| m.duration.max(0.0)).sum()
+    }
+}
+
+// Shirley and Chiu's concentric mapping from the unit square onto the unit disc, which keeps
+// evenly spread samples evenly spread
+fn concentric_disc(u: f64, v: f64) -> (f64, f64) {
+    let (a, b) = (2.0 * u - 1.0, 2.0 * v - 1.0);
+
+    if a == 0.0 && b == 0.0 {
+        return (0.0, 0.0);
     }
+
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, PI / 4.0 * (b / a))
+    } else {
+        (b, PI / 2.0 - PI / 4.0 * (a / b))
+    };
+
+    (r * theta.cos(), r * theta.sin())
 }
 
 impl Animate for Camera {
-    fn start(&mut self) {
-        todo!()
+    fn set_time(&mut self, time: f64) {
+        if self.camera_moves.is_empty() {
+            return;
+        }
+
+        let (mut location, mut look_at) = self.path_start;
+        let mut elapsed = 0.0;
+
+        for camera_move in &self.camera_moves {
+            // how much of this move is done by `time`, from 0 to 1
+            let progress = if camera_move.duration > 0.0 { ((time - elapsed) / camera_move.duration).clamp(0.0, 1.0) } else { 1.0 };
+
+            let target = camera_move.look_at.unwrap_or(look_at + Vec3::between(&location, &camera_move.to));
+
+            location = location + Vec3::between(&location, &camera_move.to) * progress;
+            look_at = look_at + Vec3::between(&look_at, &target) * progress;
+            elapsed += camera_move.duration.max(0.0);
+        }
+
+        self.location = location;
+        self.look_at = look_at;
+        self.orient();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{animate::Animate, camera::{Camera, FieldOfView, Projection}, vec3::Vec3, scene::Scene, color::Color};
+
+    fn angle(a: &Vec3, b: &Vec3) -> f64 {
+        a.unit().dot(&b.unit()).clamp(-1.0, 1.0).acos().to_degrees()
     }
 
-    fn update(&mut self, delta: f64) {
-        if !self.camera_moves.is_empty() {
-            let (move_to, duration, look_at) = self.camera_moves[0];
+    #[test]
+    fn field_of_view() {
+        let mut camera = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0));
+        camera.set_aspect(2.0);
 
-            // Move the camera to the specified point
-            self.location = self.location + (Vec3::between(&self.location, &move_to) * (delta / duration));
+        // edge to edge across the picture is the whole field of view
+        assert!((angle(&camera.ray(-0.5, 0.0).unwrap().direction, &camera.ray(0.5, 0.0).unwrap().direction) - 90.0).abs() < 1e-9);
+        assert!((camera.camera_up.length() - 0.5).abs() < 1e-9);
 
-            if let Some(lock_onto) = look_at {
-                // Rotate the camera to look at the given point
-                let direction = Vec3::between(&self.location, &lock_onto).unit();
-                self.camera_right = Vec3::J.cross(&direction).unit() * (self.width / 2.0);
-                self.camera_up = self.camera_right.cross(&direction).unit().invert() * (-self.height / 2.0);
-            }
+        // -J is up, so the top of the picture looks towards -y, and the right towards +x
+        assert!(camera.ray(0.0, -0.5).unwrap().direction.y < 0.0);
+        assert!(camera.ray(0.5, 0.0).unwrap().direction.x > 0.0);
 
-            // Snap-to and remove item from moves
-            if Vec3::between(&self.location, &move_to).length() < 0.01 {
-                self.location = move_to;
-                // TODO should probably use a Queue type
-                self.camera_moves.remove(0);
-            }
+        let vertical = Camera::new(Vec3::O, Vec3::K, FieldOfView::Vertical(60.0));
+        assert!((angle(&vertical.ray(0.0, -0.5).unwrap().direction, &vertical.ray(0.0, 0.5).unwrap().direction) - 60.0).abs() < 1e-9);
+
+        // the old film size of 4 across gave a right angle
+        let film = Camera::from_film(Vec3::O, Vec3::K, 4.0, 2.25);
+        assert!((angle(&film.ray(-0.5, 0.0).unwrap().direction, &film.ray(0.5, 0.0).unwrap().direction) - 90.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn aspect_follows_the_output() {
+        let across = |camera: &Camera| angle(&camera.ray(-0.5, 0.0).unwrap().direction, &camera.ray(0.5, 0.0).unwrap().direction);
+        let down = |camera: &Camera| angle(&camera.ray(0.0, -0.5).unwrap().direction, &camera.ray(0.0, 0.5).unwrap().direction);
+
+        // a horizontal field of view keeps its width, and the picture gets shorter as it gets wider
+        let mut horizontal = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0));
+        for aspect in [0.5, 1.0, 16.0 / 9.0] {
+            horizontal.set_aspect(aspect);
+            assert!((across(&horizontal) - 90.0).abs() < 1e-9);
+            assert!((down(&horizontal) - 2.0 * (1.0 / aspect).atan().to_degrees()).abs() < 1e-9);
+        }
+
+        // and a vertical one keeps its height
+        let mut vertical = Camera::new(Vec3::O, Vec3::K, FieldOfView::Vertical(60.0));
+        vertical.set_aspect(2.0);
+        assert!((down(&vertical) - 60.0).abs() < 1e-9);
+        assert!((across(&vertical) - 2.0 * (2.0 * 30f64.to_radians().tan()).atan().to_degrees()).abs() < 1e-9);
+    }
+
+    #[test]
+    fn up_and_roll() {
+        // looking straight down the up axis still gives a usable basis
+        for look in [Vec3::J, Vec3::J.invert()] {
+            let camera = Camera::new(Vec3::O, look, FieldOfView::Horizontal(60.0));
+
+            assert!(camera.camera_right.length() > 0.1 && camera.camera_up.length() > 0.1);
+            assert!(camera.camera_right.dot(&camera.direction).abs() < 1e-9);
+            assert!(camera.camera_up.dot(&camera.direction).abs() < 1e-9);
+            assert!(camera.camera_right.dot(&camera.camera_up).abs() < 1e-9);
         }
+
+        // turning the camera a quarter turn counter-clockwise brings what was on the left to the top
+        let level = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(60.0));
+        let rolled = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(60.0)).with_roll(90.0);
+        assert!(angle(&rolled.camera_up, &level.camera_right.invert()) < 1e-6);
+
+        // and a different up vector turns the picture with it
+        let sideways = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(60.0)).with_up(Vec3::I);
+        assert!(angle(&sideways.camera_up, &Vec3::I) < 1e-6);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn projections() {
+        let camera = |projection| Camera::new(Vec3::new(0, 0, -10), Vec3::O, FieldOfView::Horizontal(180.0)).with_projection(projection);
+
+        // every projection looks straight ahead through the middle of the picture
+        for projection in [Projection::Perspective, Projection::Orthographic, Projection::Fisheye, Projection::Equirectangular] {
+            assert!(angle(&camera(projection).ray(0.0, 0.0).unwrap().direction, &Vec3::K) < 1e-6, "{:?}", projection);
+        }
+
+        // orthographic rays are parallel, and spread out over the film instead
+        let ortho = camera(Projection::Orthographic);
+        let (left, right) = (ortho.ray(-0.5, 0.0).unwrap(), ortho.ray(0.5, 0.0).unwrap());
+        assert!(angle(&left.direction, &right.direction) < 1e-6);
+        assert!(left.origin.x < right.origin.x);
+
+        // a 180 degree fisheye looks side on at its edge, and sees nothing past its circle
+        let fisheye = camera(Projection::Fisheye);
+        assert!((angle(&fisheye.ray(0.5, 0.0).unwrap().direction, &Vec3::K) - 90.0).abs() < 1e-6);
+        assert!(fisheye.ray(0.5, 0.5).is_none());
+
+        // which fills in with the background, the same as a ray that hits nothing
+        let scene = Scene::new(fisheye, Color::new(0.2, 0.4, 0.6));
+        assert_eq!(scene.camera.trace(&scene, 0.5, 0.5, &mut rand::thread_rng()), scene.background);
+
+        // the panorama's left and right edges meet behind the camera, and its top is straight up (-y)
+        let panorama = camera(Projection::Equirectangular);
+        assert!(angle(&panorama.ray(0.5, 0.0).unwrap().direction, &Vec3::K.invert()) < 1e-6);
+        assert!(angle(&panorama.ray(-0.5, 0.0).unwrap().direction, &Vec3::K.invert()) < 1e-6);
+        assert!(angle(&panorama.ray(0.0, -0.5).unwrap().direction, &Vec3::J.invert()) < 1e-6);
+
+        // the front face of the cube sits bottom middle
+        let cubemap = camera(Projection::Cubemap);
+        assert!(angle(&cubemap.ray(0.0, 0.25).unwrap().direction, &Vec3::K) < 1e-6);
+        assert!(angle(&cubemap.ray(-1.0 / 3.0, -0.25).unwrap().direction, &Vec3::I) < 1e-6);
+    }
+
+    #[test]
+    fn depth_of_field() {
+        let camera = Camera::new(Vec3::O, Vec3::new(0, 0, 5), FieldOfView::Horizontal(90.0)).with_aperture(0.5);
+        assert_eq!(camera.focus_distance(), 5.0);
+
+        // rays from opposite edges of the lens leave from different places but meet again on the focal plane
+        let (a, b) = (camera.lens_ray(0.25, 0.1, 0.0, 0.5).unwrap(), camera.lens_ray(0.25, 0.1, 1.0, 0.5).unwrap());
+        assert!((a.origin.x - b.origin.x).abs() > 0.9);
+
+        let on_focal_plane = |ray: &crate::structs::ray::Ray| ray.origin + ray.direction * ((5.0 - ray.origin.z) / ray.direction.z);
+        assert!(Vec3::between(&on_focal_plane(&a), &on_focal_plane(&b)).length() < 1e-9);
+        assert!(Vec3::between(&on_focal_plane(&a), &on_focal_plane(&camera.ray(0.25, 0.1).unwrap())).length() < 1e-9);
+
+        // a 90 degree lens on a full-frame sensor is 18mm, so f/2 is 4.5mm across the radius
+        let f_stop = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)).with_f_stop(2.0);
+        assert!((f_stop.focal_length() - 0.018).abs() < 1e-9);
+        assert!((f_stop.aperture - 0.0045).abs() < 1e-9);
+    }
+
+    #[test]
+    fn moves_follow_time() {
+        let mut camera = Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0));
+        camera.add_camera_move(Vec3::new(10, 0, 0), 2.0, None);
+        camera.add_camera_move(Vec3::new(10, 0, 10), 1.0, Some(Vec3::new(10, 0, 20)));
+        assert_eq!(camera.path_duration(), 3.0);
+
+        // steady, and done exactly when the durations say
+        camera.set_time(0.5);
+        assert_eq!((camera.location, camera.look_at), (Vec3::new(2.5, 0.0, 0.0), Vec3::new(2.5, 0.0, 1.0)));
+        camera.set_time(2.0);
+        assert_eq!((camera.location, camera.look_at), (Vec3::new(10, 0, 0), Vec3::new(10, 0, 1)));
+
+        // turning towards the new point on the way, and staying put once the path runs out
+        camera.set_time(2.5);
+        assert_eq!((camera.location, camera.look_at), (Vec3::new(10.0, 0.0, 5.0), Vec3::new(10.0, 0.0, 10.5)));
+        camera.set_time(10.0);
+        assert_eq!((camera.location, camera.look_at), (Vec3::new(10, 0, 10), Vec3::new(10, 0, 20)));
+
+        // going back in time is as good as getting there from the start
+        camera.set_time(0.0);
+        assert_eq!((camera.location, camera.look_at), (Vec3::O, Vec3::K));
+        assert_eq!(camera.direction, Vec3::K);
+    }
+}
@@ -1,61 +1,106 @@
-use std::cmp::min;
+use std::ops::{Add, AddAssign, Mul};
 use anyhow::bail;
 use image::Rgba;
 
-pub fn color_add(lhs: &Rgba<u8>, rhs: &Rgba<u8>) -> Rgba<u8> {
-    // default to full-alpha, clamp rgb vals
-    let new_r = min((lhs.0[0] as u64) + (rhs.0[0] as u64), 0xFF) as u8;
-    let new_g = min((lhs.0[1] as u64) + (rhs.0[1] as u64), 0xFF) as u8;
-    let new_b = min((lhs.0[2] as u64) + (rhs.0[2] as u64), 0xFF) as u8;
-
-    Rgba ([ 
-        new_r, 
-        new_g, 
-        new_b, 
-        0xFF 
-    ])
+/// Linear RGB, one float per channel. Nothing gets clamped while shading, so channels can go
+/// past 1.0 (bright lights, stacked highlights); `to_rgba` squeezes the finished pixel into 8 bits.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Color {
+    pub r: f64,
+    pub g: f64,
+    pub b: f64
 }
 
-pub fn color_multiply(lhs: &Rgba<u8>, rhs: &Rgba<u8>) -> Rgba<u8> {
-    let new_r = ((lhs.0[0] as u64) * (rhs.0[0] as u64) / 0xFF) as u8;
-    let new_g = ((lhs.0[1] as u64) * (rhs.0[1] as u64) / 0xFF) as u8;
-    let new_b = ((lhs.0[2] as u64) * (rhs.0[2] as u64) / 0xFF) as u8;
-
-    Rgba ([ 
-        new_r, 
-        new_g, 
-        new_b, 
-        0xFF 
-    ])
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Color::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b)
+    }
+}
+
+impl AddAssign for Color {
+    fn add_assign(&mut self, rhs: Self) {
+        *self = *self + rhs;
+    }
+}
+
+// Channel by channel, e.g. a light's colour filtered by a surface's
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b)
+    }
 }
 
-pub fn color_scale<T>(color: &Rgba<u8>, factor: T) -> Rgba<u8> 
+impl<T> Mul<T> for Color
     where T: Into<f64> + Copy
 {
-    let new_r = (color.0[0] as f64 * factor.into()) as u8;
-    let new_g = (color.0[1] as f64 * factor.into()) as u8;
-    let new_b = (color.0[2] as f64 * factor.into()) as u8;
-
-    Rgba ([
-        new_r,
-        new_g,
-        new_b,
-        0xFF
-    ])
+    type Output = Color;
+
+    fn mul(self, rhs: T) -> Self::Output {
+        let factor = rhs.into();
+        Color::new(self.r * factor, self.g * factor, self.b * factor)
+    }
 }
 
-pub fn color_from_hex(hex_string: &str) -> Result<Rgba<u8>, anyhow::Error> {
-    if hex_string.len() == 7 {
-        let r = u8::from_str_radix(&hex_string[1..3], 16);
-        let g = u8::from_str_radix(&hex_string[3..5], 16);
-        let b = u8::from_str_radix(&hex_string[5..7], 16);
-        Ok(Rgba([r?, g?, b?, 0xFF]))
-    } else if hex_string.len() == 9 {
+impl Color {
+    pub const BLACK: Color = Color { r: 0.0, g: 0.0, b: 0.0 };
+    pub const WHITE: Color = Color { r: 1.0, g: 1.0, b: 1.0 };
+
+    pub fn new<T>(r: T, g: T, b: T) -> Self
+        where T: Into<f64>
+    {
+        Self {
+            r: r.into(),
+            g: g.into(),
+            b: b.into()
+        }
+    }
+
+    pub fn from_rgba(color: &Rgba<u8>) -> Self {
+        Color::new(color.0[0] as f64 / 255.0, color.0[1] as f64 / 255.0, color.0[2] as f64 / 255.0)
+    }
+
+    /// Clamps each channel to [0, 1] and rounds to 8 bits, with full alpha.
+    pub fn to_rgba(self) -> Rgba<u8> {
+        let [r, g, b] = [self.r, self.g, self.b].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+        Rgba([r, g, b, 0xFF])
+    }
+
+    /// Decodes 8-bit sRGB (what hex codes and colour pickers give you) into linear light.
+    pub fn from_srgb(color: &Rgba<u8>) -> Self {
+        Color::from_rgba(color).map(|c| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) })
+    }
+
+    /// The other way round: clamps to [0, 1], applies the sRGB transfer curve and rounds to 8 bits.
+    pub fn to_srgb(self) -> Rgba<u8> {
+        self.map(|c| {
+            let c = c.clamp(0.0, 1.0);
+            if c <= 0.0031308 { c * 12.92 } else { 1.055 * c.powf(1.0 / 2.4) - 0.055 }
+        }).to_rgba()
+    }
+
+    pub fn map<F>(&self, f: F) -> Self
+        where F: Fn(f64) -> f64
+    {
+        Color::new(f(self.r), f(self.g), f(self.b))
+    }
+}
+
+// Hex codes are sRGB, and get linearised here. Alpha (the 9-character form) is accepted but has nowhere to go, so it's dropped
+pub fn color_from_hex(hex_string: &str) -> Result<Color, anyhow::Error> {
+    // the slicing below counts bytes, which only line up with characters in ASCII
+    if !hex_string.is_ascii() {
+        bail!("Invalid hex string ({})", hex_string)
+    }
+
+    if hex_string.len() == 7 || hex_string.len() == 9 {
         let r = u8::from_str_radix(&hex_string[1..3], 16);
         let g = u8::from_str_radix(&hex_string[3..5], 16);
         let b = u8::from_str_radix(&hex_string[5..7], 16);
-        let a = u8::from_str_radix(&hex_string[7..9], 16);
-        Ok(Rgba([r?, g?, b?, a?]))
+        Ok(Color::from_srgb(&Rgba([r?, g?, b?, 0xFF])))
     } else {
         bail!("Invalid hex string length ({})", hex_string)
     }
@@ -67,27 +112,52 @@ mod tests {
 
     use super::Rgba;
 
-    fn color_equal(color_a: Rgba<u8>, color_b: Rgba<u8>) -> bool {
-        color_a.0[0] == color_b.0[0] &&
-        color_a.0[1] == color_b.0[1] &&
-        color_a.0[2] == color_b.0[2] &&
-        color_a.0[3] == color_b.0[3]
+    #[test]
+    fn test_color_scale() {
+        assert_eq!((Color::from_rgba(&Rgba([16, 16, 16, 255])) * 2.0).to_rgba(), Rgba([32, 32, 32, 255]));
+        assert_eq!((Color::from_rgba(&Rgba([32, 32, 32, 255])) * 0.5).to_rgba(), Rgba([16, 16, 16, 255]));
+
+        // dim things don't lose precision on the way through, and bright ones only clip at the end
+        assert_eq!((Color::from_rgba(&Rgba([3, 3, 3, 255])) * 0.01 * 100.0).to_rgba(), Rgba([3, 3, 3, 255]));
+        assert_eq!((Color::WHITE * 4.0 * 0.5).to_rgba(), Rgba([255, 255, 255, 255]));
+        assert_eq!((Color::WHITE * 4.0 * 0.125).to_rgba(), Rgba([128, 128, 128, 255]));
     }
 
     #[test]
-    fn test_color_scale() {
-        assert!(
-            color_equal(
-                color_scale(&Rgba([16, 16, 16, 255]), 2.0), 
-                Rgba([32, 32, 32, 255])
-            )
-        );
-
-        assert!(
-            color_equal(
-                color_scale(&Rgba([32, 32, 32, 255]), 0.5), 
-                Rgba([16, 16, 16, 255])
-            )
-        );
-    }
-}
\ No newline at end of file
+    fn test_srgb() {
+        for value in 0..=255 {
+            let color = Rgba([value, 255 - value, value / 2, 255]);
+            assert_eq!(Color::from_srgb(&color).to_srgb(), color);
+        }
+
+        // linear mid-grey is a lot lighter than 128 once encoded
+        assert_eq!((Color::WHITE * 0.5).to_srgb(), Rgba([188, 188, 188, 255]));
+    }
+
+    #[test]
+    fn test_color_arithmetic() {
+        let (a, b) = (Color::new(0.5, 1.0, 2.0), Color::new(0.25, 0.5, 4.0));
+
+        assert_eq!(a + b, Color::new(0.75, 1.5, 6.0));
+        assert_eq!(a * b, Color::new(0.125, 0.5, 8.0));
+        assert_eq!(a * 2, Color::new(1, 2, 4));
+        assert_eq!(a.map(|c| c - 0.5), Color::new(0.0, 0.5, 1.5));
+
+        let mut sum = Color::BLACK;
+        sum += a;
+        sum += a;
+        assert_eq!(sum, a * 2.0);
+    }
+
+    #[test]
+    fn test_hex() {
+        assert_eq!(color_from_hex("#FF0000").unwrap(), Color::new(1, 0, 0));
+        assert_eq!(color_from_hex("#00ff00").unwrap(), Color::new(0, 1, 0));
+        assert_eq!(color_from_hex("#0000FF80").unwrap(), Color::new(0, 0, 1));
+        assert_eq!(color_from_hex("#808080").unwrap().to_srgb(), Rgba([128, 128, 128, 255]));
+
+        for bad in ["#FFF", "#GG0000", "FF0000", "#1é234", "#ÿÿÿ"] {
+            assert!(color_from_hex(bad).is_err(), "{}", bad);
+        }
+    }
+}
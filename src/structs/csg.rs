@@ -0,0 +1,174 @@
+use super::{shape::Shape, ray::Ray, hit::Hit, bvh::Aabb};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    // everything in the first shape that isn't in any of the others
+    Difference
+}
+
+impl CsgOperation {
+    fn inside(&self, in_a: bool, in_b: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_a || in_b,
+            CsgOperation::Intersection => in_a && in_b,
+            CsgOperation::Difference => in_a && !in_b
+        }
+    }
+}
+
+/// Constructive solid geometry node. With more than two children the operation folds left,
+/// so a difference is `children[0] - children[1] - children[2] ...`.
+pub struct Csg<'a> {
+    pub operation: CsgOperation,
+    pub children: Vec<Box<dyn Shape + Send + Sync + 'a>>
+}
+
+impl<'a> Csg<'a> {
+    // how close to perpendicular to the surface's normal a ray has to be to count as only touching it
+    const GRAZING: f64 = 1e-9;
+
+    pub fn new(operation: CsgOperation, children: Vec<Box<dyn Shape + Send + Sync + 'a>>) -> Self {
+        Self {
+            operation,
+            children
+        }
+    }
+
+    // Walks both sorted hit lists together, tracking whether the ray is inside each operand,
+    // and keeps only the hits where the combined solid's inside/outside state actually changes.
+    fn combine<'h>(&self, a: Vec<Hit<'h>>, b: Vec<Hit<'h>>) -> Vec<Hit<'h>> {
+        // If the first crossing is a way out, the ray started inside
+        let mut in_a = a.first().is_some_and(|hit| !hit.front_face);
+        let mut in_b = b.first().is_some_and(|hit| !hit.front_face);
+
+        let mut merged: Vec<(bool, Hit)> = a.into_iter().map(|hit| (true, hit)).chain(b.into_iter().map(|hit| (false, hit))).collect();
+        merged.sort_by(|x, y| x.1.distance.total_cmp(&y.1.distance));
+
+        let mut res = Vec::new();
+
+        for (from_a, hit) in merged {
+            let was_inside = self.operation.inside(in_a, in_b);
+
+            // a front-face hit is the ray entering that operand
+            if from_a { in_a = hit.front_face; } else { in_b = hit.front_face; }
+
+            if self.operation.inside(in_a, in_b) != was_inside {
+                if !from_a && self.operation == CsgOperation::Difference {
+                    // surfaces of a subtracted solid face the other way in the result
+                    res.push(Hit {
+                        normal: hit.normal.invert(),
+                        shading_normal: hit.shading_normal.invert(),
+                        front_face: !hit.front_face,
+                        ..hit
+                    });
+                } else {
+                    res.push(hit);
+                }
+            }
+        }
+
+        res
+    }
+}
+
+impl Shape for Csg<'_> {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
+        // A ray that only grazes a surface (a sphere's single tangent root, say) touches it without going
+        // in or out, and would throw off the inside/outside tracking in `combine` for every hit after it
+        fn sorted<'h>(shape: &'h (dyn Shape + Send + Sync + '_), ray: &Ray) -> Vec<Hit<'h>> {
+            let mut hits = shape.intersections(ray);
+            hits.retain(|hit| ray.direction.dot(&hit.normal).abs() > Csg::GRAZING);
+            hits.sort_by(|x, y| x.distance.total_cmp(&y.distance));
+            hits
+        }
+
+        let mut children = self.children.iter();
+
+        let Some(first) = children.next() else {
+            return vec![];
+        };
+
+        children.fold(sorted(first.as_ref(), ray), |acc, child| self.combine(acc, sorted(child.as_ref(), ray)))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let mut bounds = self.children.iter().map(|child| child.bounds());
+
+        match self.operation {
+            // a union is only bounded if all of its parts are
+            CsgOperation::Union => bounds.try_fold(Aabb::EMPTY, |acc, b| b.map(|b| acc.union(&b))),
+            // an intersection fits inside any bounded part
+            CsgOperation::Intersection => bounds.flatten().reduce(|acc, b| acc.intersection(&b)),
+            // a difference never grows past the first shape
+            CsgOperation::Difference => bounds.next().flatten()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{csg::{Csg, CsgOperation}, shape::Shape, prism::Prism, sphere::Sphere, appearance::Appearance, finish::Finish, vec3::Vec3, ray::Ray, color::Color};
+
+    fn solids() -> Vec<Box<dyn Shape + Send + Sync>> {
+        let appearance = Appearance::new(Color::WHITE, Finish::DEFAULT);
+
+        vec![
+            Box::new(Prism::new(Vec3::new(-1, -1, -1), Vec3::new(1, 1, 1), appearance.clone())),
+            Box::new(Sphere::new(Vec3::O, 0.5, appearance))
+        ]
+    }
+
+    fn distances(csg: &Csg, ray: &Ray) -> Vec<f64> {
+        let mut hits: Vec<f64> = csg.intersections(ray).iter().map(|hit| hit.distance).collect();
+        hits.sort_by(f64::total_cmp);
+        hits
+    }
+
+    // along the z axis, just off centre, through the box and the ball inside it
+    fn ray() -> Ray {
+        Ray::new(Vec3::new(0.0, 0.1, -5.0), Vec3::K)
+    }
+
+    #[test]
+    fn difference_hollows_out() {
+        let ray = ray();
+        let csg = Csg::new(CsgOperation::Difference, solids());
+        let hits = csg.intersections(&ray);
+
+        assert_eq!(hits.len(), 4);
+
+        // the ray leaves the solid where it enters the hollow, and the hollow's wall faces into it
+        let cavity = hits.iter().find(|hit| hit.distance > 4.0 && hit.distance < 5.0).unwrap();
+        assert!(!cavity.front_face);
+        assert!(cavity.normal.z > 0.0);
+    }
+
+    #[test]
+    fn union_and_intersection() {
+        let ray = ray();
+
+        let union = distances(&Csg::new(CsgOperation::Union, solids()), &ray);
+        assert_eq!(union.len(), 2);
+        assert!((union[0] - 4.0).abs() < 1e-9 && (union[1] - 6.0).abs() < 1e-9);
+
+        let intersection = distances(&Csg::new(CsgOperation::Intersection, solids()), &ray);
+        assert_eq!(intersection.len(), 2);
+        assert!(intersection[0] > 4.5 && intersection[1] < 5.5);
+    }
+
+    #[test]
+    fn grazing_hits_are_ignored() {
+        let appearance = Appearance::new(Color::WHITE, Finish::DEFAULT);
+        let pair = || -> Vec<Box<dyn Shape + Send + Sync>> { vec![
+            // touched at z = 5 by a ray along the z axis, without it going in
+            Box::new(Sphere::new(Vec3::new(0, 1, 5), 1.0, appearance.clone())),
+            Box::new(Sphere::new(Vec3::new(0, 0, 10), 1.0, appearance.clone()))
+        ] };
+        let ray = Ray::new(Vec3::O, Vec3::K);
+
+        assert_eq!(distances(&Csg::new(CsgOperation::Union, pair()), &ray), vec![9.0, 11.0]);
+        assert!(Csg::new(CsgOperation::Intersection, pair()).intersections(&ray).is_empty());
+    }
+}
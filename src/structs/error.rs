@@ -0,0 +1,65 @@
+use std::{io, path::{Path, PathBuf}};
+
+use thiserror::Error;
+
+/// Everything that can go wrong between reading a scene and writing out what it rendered.
+/// Rendering itself can't fail; by the time a scene exists, it's been checked.
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Unable to read {}: {source}", .path.display())]
+    Read { path: PathBuf, #[source] source: io::Error },
+
+    #[error("Unable to read {}: {source}", .path.display())]
+    ImageRead { path: PathBuf, #[source] source: image::ImageError },
+
+    // an image sequence with nothing in it, or frames that don't match
+    #[error("{}: {message}", .path.display())]
+    Sequence { path: PathBuf, message: String },
+
+    #[error("Unable to load mesh {}: {source}", .path.display())]
+    Mesh { path: PathBuf, #[source] source: tobj::LoadError },
+
+    #[error("No models defined in {}", .path.display())]
+    EmptyMesh { path: PathBuf },
+
+    // `position` is the line and column, when the mistake can be pinned down
+    #[error("{}{message}", scene_location(.file, .position))]
+    Scene { file: Option<PathBuf>, position: Option<(usize, usize)>, message: String },
+
+    #[error("{} isn't a format we can write here (expected {expected})", .path.display())]
+    UnsupportedFormat { path: PathBuf, expected: String },
+
+    #[error("Unable to write {}: {source}", .path.display())]
+    Write { path: PathBuf, #[source] source: io::Error },
+
+    #[error("Unable to write {}: {source}", .path.display())]
+    Image { path: PathBuf, #[source] source: image::ImageError },
+
+    #[error("Unable to write {}: {source}", .path.display())]
+    Gif { path: PathBuf, #[source] source: gif::EncodingError },
+
+    #[error("Unable to write {}: {source}", .path.display())]
+    Video { path: PathBuf, #[source] source: video_rs::Error },
+
+    // for writers whose errors don't implement `std::error::Error`
+    #[error("Unable to write {}: {message}", .path.display())]
+    Output { path: PathBuf, message: String }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn write(path: &Path) -> impl FnOnce(io::Error) -> Error + '_ {
+        move |source| Error::Write { path: path.to_path_buf(), source }
+    }
+}
+
+fn scene_location(file: &Option<PathBuf>, position: &Option<(usize, usize)>) -> String {
+    let file = file.as_ref().map_or(String::new(), |file| format!("{}:", file.display()));
+
+    match position {
+        Some((line, column)) => format!("{}{}:{}: ", file, line, column),
+        None if file.is_empty() => String::new(),
+        None => format!("{} ", file)
+    }
+}
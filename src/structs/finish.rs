@@ -1,38 +1,57 @@
-use image::Rgba;
-
-use super::{vec3::Vec3, light::Light, color::color_scale};
+use super::{vec3::Vec3, light::Light, color::Color};
 
 #[derive(Clone)]
 pub struct Finish {
     pub ambient: f64,
     pub diffuse: f64,
     pub shiny: f64,
-    pub reflect: f64
+    pub reflect: f64,
+    // fraction of light that passes through the surface, and the index of refraction it bends by
+    pub transmit: f64,
+    pub ior: f64,
+    // how quickly light travelling inside a (closed) transparent shape takes on its colour, per unit distance
+    pub absorb: f64
 }
 
 impl Finish {
-    pub const DEFAULT: Finish = Finish { ambient: 0.0, diffuse: 1.0, shiny: 0.0, reflect: 0.0};
+    pub const DEFAULT: Finish = Finish { ambient: 0.0, diffuse: 1.0, shiny: 0.0, reflect: 0.0, transmit: 0.0, ior: 1.0, absorb: 0.0 };
 
     pub fn new(ambient: f64, diffuse: f64, shiny: f64, reflect: f64) -> Self {
         Self {
             ambient,
             diffuse,
             shiny,
-            reflect
+            reflect,
+            ..Finish::DEFAULT
         }
     }
 
-    pub fn add_highlight(&self, reflex: &Vec3, light: &Light, light_vector: &Vec3) -> Rgba<u8> {
+    pub fn with_transmission(self, transmit: f64, ior: f64) -> Self {
+        Self { transmit, ior, ..self }
+    }
+
+    pub fn with_absorption(self, absorb: f64) -> Self {
+        Self { absorb, ..self }
+    }
+
+    /// Schlick's approximation of the share of light reflected off a transparent surface.
+    /// `cos` is the cosine of the angle to the normal on the less dense side.
+    pub fn fresnel(&self, cos: f64) -> f64 {
+        let r0 = ((self.ior - 1.0) / (self.ior + 1.0)).powi(2);
+        r0 + (1.0 - r0) * (1.0 - cos).powi(5)
+    }
+
+    pub fn add_highlight(&self, reflex: &Vec3, light: &Light, light_vector: &Vec3) -> Color {
         if self.shiny <= 0.0 {
-            Rgba([0, 0, 0, 255])
+            Color::BLACK
         } else {
             let mut intensity = reflex.unit().dot(&light_vector.unit());
             if intensity <= 0.0 {
-                Rgba([0, 0, 0, 255])
+                Color::BLACK
             } else {
                 let exponent = 32.0 * self.shiny * self.shiny;
                 intensity = intensity.powf(exponent);
-                color_scale(&light.color, self.shiny * intensity)
+                light.color * (self.shiny * intensity)
             }
         }
     }
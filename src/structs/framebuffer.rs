@@ -0,0 +1,47 @@
+use image::Rgba;
+
+use super::{color::Color, tonemap::ToneMapper};
+
+/// Linear colour for every pixel of one rendered image, before any tone mapping.
+/// Stored row by row, starting from the top left.
+pub struct Framebuffer {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+    // how many samples went into each pixel, in the same order
+    pub samples: Vec<usize>
+}
+
+impl Framebuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![Color::BLACK; width * height],
+            samples: vec![0; width * height]
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color, samples: usize) {
+        self.pixels[y * self.width + x] = color;
+        self.samples[y * self.width + x] = samples;
+    }
+
+    /// Display-ready pixels, in the same order as `pixels`.
+    pub fn to_rgba(&self, tonemapper: &ToneMapper) -> Vec<Rgba<u8>> {
+        self.pixels.iter().map(|color| tonemapper.map(color)).collect()
+    }
+
+    /// Sample counts as colours, for tuning adaptive sampling: blue for the fewest samples in the
+    /// image, through to red for the most.
+    pub fn heatmap(&self) -> Vec<Rgba<u8>> {
+        let fewest = self.samples.iter().copied().min().unwrap_or(0);
+        let most = self.samples.iter().copied().max().unwrap_or(0);
+        let range = (most - fewest).max(1) as f64;
+
+        self.samples.iter().map(|&count| {
+            let heat = (count - fewest) as f64 / range;
+            Color::new(heat, 0.0, 1.0 - heat).to_rgba()
+        }).collect()
+    }
+}
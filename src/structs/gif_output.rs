@@ -0,0 +1,229 @@
+use std::{fs::File, io::BufWriter, path::{Path, PathBuf}, str::FromStr};
+
+use color_quant::NeuQuant;
+use gif::{Encoder, Frame, Repeat};
+use image::Rgba;
+
+use super::error::{Error, Result};
+
+/// Where a GIF's 256 colours come from.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GifPalette {
+    // one palette picked from every frame at once; steadier colours, but the frames are kept in memory until `finish`
+    Global,
+    // each frame picks its own, so gradual changes in colour come through better, at the price of some flicker
+    PerFrame
+}
+
+impl FromStr for GifPalette {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "global" => Ok(GifPalette::Global),
+            "per-frame" | "local" => Ok(GifPalette::PerFrame),
+            other => Err(format!("Unknown GIF palette `{}` (expected global or per-frame)", other))
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct GifOptions {
+    pub palette: GifPalette,
+    // Floyd-Steinberg error diffusion, which trades banding in gradients for fine noise
+    pub dither: bool,
+    // how many times to play the animation; 0 loops forever
+    pub loops: u16,
+    pub frame_rate: usize
+}
+
+enum Frames {
+    Streaming(Encoder<BufWriter<File>>),
+    // RGB, waiting for the global palette
+    Buffered(Vec<Vec<u8>>)
+}
+
+/// Writes frames into an animated GIF one at a time. Delays are in hundredths of a second, so they
+/// get rounded so as to keep the animation's overall timing; viewers tend to slow down anything
+/// faster than 50 frames per second.
+pub struct GifWriter {
+    path: PathBuf,
+    width: u16,
+    height: u16,
+    options: GifOptions,
+    frames: Frames,
+    written: usize
+}
+
+impl GifWriter {
+    // NeuQuant looks at one pixel in this many while training; lower is slower and more faithful
+    const SAMPLE_FACTOR: i32 = 10;
+    // the most pixels the global palette gets trained on, however long the animation is
+    const GLOBAL_TRAINING_PIXELS: usize = 1 << 20;
+
+    pub fn create(path: &Path, width: usize, height: usize, options: GifOptions) -> Result<Self> {
+        let (Ok(w), Ok(h)) = (u16::try_from(width), u16::try_from(height)) else {
+            return Err(Error::Output { path: path.to_path_buf(), message: format!("{}x{} is too large for a GIF", width, height) });
+        };
+
+        let frames = match options.palette {
+            GifPalette::Global => Frames::Buffered(Vec::new()),
+            GifPalette::PerFrame => Frames::Streaming(GifWriter::encoder(path, w, h, &[], options.loops)?)
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            width: w,
+            height: h,
+            options,
+            frames,
+            written: 0
+        })
+    }
+
+    pub fn add_frame(&mut self, pixels: &[Rgba<u8>]) -> Result<()> {
+        let rgb: Vec<u8> = pixels.iter().flat_map(|p| [p.0[0], p.0[1], p.0[2]]).collect();
+
+        match &mut self.frames {
+            Frames::Buffered(frames) => frames.push(rgb),
+            Frames::Streaming(_) => {
+                let quantizer = NeuQuant::new(GifWriter::SAMPLE_FACTOR, 256, &rgba(&rgb));
+                let mut frame = Frame::from_indexed_pixels(self.width, self.height, &self.indices(&rgb, &quantizer), None);
+                frame.palette = Some(quantizer.color_map_rgb());
+
+                self.write(frame)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        if let Frames::Buffered(frames) = &mut self.frames {
+            let frames = std::mem::take(frames);
+
+            // every frame gets a say in the palette, thinned out evenly if there's a lot of them
+            let total = frames.iter().map(|f| f.len() / 3).sum::<usize>();
+            let step = total.div_ceil(GifWriter::GLOBAL_TRAINING_PIXELS).max(1);
+            let training: Vec<u8> = frames.iter().flat_map(|f| f.chunks_exact(3).step_by(step)).flatten().copied().collect();
+
+            let quantizer = NeuQuant::new(GifWriter::SAMPLE_FACTOR, 256, &rgba(&training));
+            self.frames = Frames::Streaming(GifWriter::encoder(&self.path, self.width, self.height, &quantizer.color_map_rgb(), self.options.loops)?);
+
+            for rgb in &frames {
+                let frame = Frame::from_indexed_pixels(self.width, self.height, &self.indices(rgb, &quantizer), None);
+                self.write(frame)?;
+            }
+        }
+
+        match self.frames {
+            Frames::Streaming(encoder) => encoder.into_inner().map(|_| ()).map_err(Error::write(&self.path)),
+            Frames::Buffered(_) => unreachable!("the global palette gets written above")
+        }
+    }
+
+    fn encoder(path: &Path, width: u16, height: u16, palette: &[u8], loops: u16) -> Result<Encoder<BufWriter<File>>> {
+        let file = File::create(path).map_err(Error::write(path))?;
+        let gif_error = |source| Error::Gif { path: path.to_path_buf(), source };
+        let mut encoder = Encoder::new(BufWriter::new(file), width, height, palette).map_err(gif_error)?;
+
+        // the loop count in the file is repeats after the first play; leaving it out plays just once
+        match loops {
+            0 => encoder.set_repeat(Repeat::Infinite).map_err(gif_error)?,
+            1 => (),
+            n => encoder.set_repeat(Repeat::Finite(n - 1)).map_err(gif_error)?
+        }
+
+        Ok(encoder)
+    }
+
+    fn write(&mut self, mut frame: Frame) -> Result<()> {
+        // hundredths of a second, rounded so that the total stays in step with the frame rate
+        let at = |n: usize| (n as f64 * 100.0 / self.options.frame_rate as f64).round() as usize;
+        frame.delay = (at(self.written + 1) - at(self.written)).clamp(1, u16::MAX as usize) as u16;
+        self.written += 1;
+
+        match &mut self.frames {
+            Frames::Streaming(encoder) => encoder.write_frame(&frame).map_err(|source| Error::Gif { path: self.path.clone(), source }),
+            Frames::Buffered(_) => unreachable!("frames are only written once there's an encoder")
+        }
+    }
+
+    // Palette indices for an RGB frame, optionally spreading each pixel's rounding error onto its neighbours
+    fn indices(&self, rgb: &[u8], quantizer: &NeuQuant) -> Vec<u8> {
+        let width = self.width as usize;
+
+        if !self.options.dither {
+            return rgb.chunks_exact(3).map(|p| quantizer.index_of(&[p[0], p[1], p[2], 255]) as u8).collect();
+        }
+
+        let mut wanted: Vec<f32> = rgb.iter().map(|&c| c as f32).collect();
+        let mut indices = Vec::with_capacity(rgb.len() / 3);
+
+        for i in 0..rgb.len() / 3 {
+            let pixel: Vec<u8> = wanted[i * 3..i * 3 + 3].iter().map(|&c| c.round().clamp(0.0, 255.0) as u8).collect();
+            let index = quantizer.index_of(&[pixel[0], pixel[1], pixel[2], 255]);
+            let got = quantizer.lookup(index).unwrap_or([0, 0, 0, 255]);
+            indices.push(index as u8);
+
+            let (x, last_row) = (i % width, i / width + 1 >= self.height as usize);
+
+            for c in 0..3 {
+                let error = wanted[i * 3 + c] - got[c] as f32;
+                let mut spread = |offset: usize, share: f32| wanted[offset * 3 + c] += error * share;
+
+                if x + 1 < width { spread(i + 1, 7.0 / 16.0); }
+                if !last_row {
+                    if x > 0 { spread(i + width - 1, 3.0 / 16.0); }
+                    spread(i + width, 5.0 / 16.0);
+                    if x + 1 < width { spread(i + width + 1, 1.0 / 16.0); }
+                }
+            }
+        }
+
+        indices
+    }
+}
+
+fn rgba(rgb: &[u8]) -> Vec<u8> {
+    rgb.chunks_exact(3).flat_map(|p| [p[0], p[1], p[2], 255]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs::{self, File}};
+
+    use image::Rgba;
+
+    use crate::structs::gif_output::{GifOptions, GifPalette, GifWriter};
+
+    #[test]
+    fn writes_animation() {
+        for (palette, dither) in [(GifPalette::Global, false), (GifPalette::PerFrame, true)] {
+            let path = env::temp_dir().join(format!("eyebeam_test_{:?}.gif", palette));
+            let mut writer = GifWriter::create(&path, 8, 4, GifOptions { palette, dither, loops: 0, frame_rate: 30 }).unwrap();
+
+            // a gradient, getting brighter from frame to frame
+            for frame in 0..3u8 {
+                let pixels: Vec<Rgba<u8>> = (0..32u8).map(|i| Rgba([i * 8, frame * 100, 255 - i * 8, 255])).collect();
+                writer.add_frame(&pixels).unwrap();
+            }
+
+            writer.finish().unwrap();
+
+            let mut options = gif::DecodeOptions::new();
+            options.set_color_output(gif::ColorOutput::RGBA);
+            let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+
+            let mut delays = Vec::new();
+            while let Some(frame) = decoder.read_next_frame().unwrap() {
+                assert_eq!((frame.width, frame.height), (8, 4));
+                delays.push(frame.delay);
+            }
+
+            // 30 frames a second doesn't go evenly into hundredths, so the delays take turns
+            assert_eq!(delays, vec![3, 4, 3], "{:?}", palette);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
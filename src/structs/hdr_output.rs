@@ -0,0 +1,175 @@
+use std::{fs::{self, File}, io::{self, BufWriter, Write}, path::Path};
+
+use image::{codecs::hdr::HdrEncoder, Rgb, Rgb32FImage};
+
+use super::{error::{Error, Result}, framebuffer::Framebuffer};
+
+// Formats that keep the framebuffer's linear floats as they are, with no exposure or tone mapping
+const FLOAT_EXTENSIONS: &[&str] = &["hdr", "exr", "pfm"];
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|ext| ext.to_string_lossy().to_ascii_lowercase())
+}
+
+/// Whether `path` names one of the floating-point formats `save_float` can write.
+pub fn is_float_format(path: &Path) -> bool {
+    extension(path).is_some_and(|ext| FLOAT_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Writes the raw radiance in `framebuffer` as Radiance RGBE (.hdr), OpenEXR (.exr) or a portable float map (.pfm),
+/// picked by the file extension.
+pub fn save_float(framebuffer: &Framebuffer, path: &Path) -> Result<()> {
+    let pixels: Vec<Rgb<f32>> = framebuffer.pixels.iter().map(|c| Rgb([c.r as f32, c.g as f32, c.b as f32])).collect();
+    let image_error = |source| Error::Image { path: path.to_path_buf(), source };
+
+    match extension(path).as_deref() {
+        Some("hdr") => {
+            let file = File::create(path).map_err(Error::write(path))?;
+            HdrEncoder::new(BufWriter::new(file)).encode(&pixels, framebuffer.width, framebuffer.height).map_err(image_error)?;
+        },
+        Some("exr") => {
+            let raw: Vec<f32> = pixels.iter().flat_map(|p| p.0).collect();
+            let image = Rgb32FImage::from_raw(framebuffer.width as u32, framebuffer.height as u32, raw)
+                .ok_or_else(|| Error::Output { path: path.to_path_buf(), message: "the framebuffer's size doesn't match its pixels".into() })?;
+            image.save(path).map_err(image_error)?;
+        },
+        Some("pfm") => save_pfm(&pixels, framebuffer.width, path).map_err(Error::write(path))?,
+        _ => return Err(Error::UnsupportedFormat { path: path.to_path_buf(), expected: FLOAT_EXTENSIONS.join(", ") })
+    }
+
+    Ok(())
+}
+
+// Simple enough not to need a crate: a text header, then little-endian floats with the *bottom* row first
+fn save_pfm(pixels: &[Rgb<f32>], width: usize, path: &Path) -> io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+
+    // a negative scale marks the data as little-endian
+    write!(out, "PF\n{} {}\n-1.0\n", width, pixels.len() / width)?;
+
+    for row in pixels.chunks(width).rev() {
+        for value in row.iter().flat_map(|p| p.0) {
+            out.write_all(&value.to_le_bytes())?;
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Reads a portable float map, which `image` can't: what `save_float` writes, or any other colour (`PF`)
+/// or greyscale (`Pf`) one in either byte order. Returns the width, the height and the pixels, top row first.
+pub fn load_pfm(path: &Path) -> io::Result<(usize, usize, Vec<[f32; 3]>)> {
+    let bytes = fs::read(path)?;
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, format!("not a portable float map ({})", message));
+
+    // the type, width, height and scale, split by whitespace, then exactly one whitespace byte before the data
+    let mut fields = Vec::new();
+    let mut at = 0;
+
+    while fields.len() < 4 {
+        while bytes.get(at).is_some_and(u8::is_ascii_whitespace) {
+            at += 1;
+        }
+
+        let start = at;
+        while bytes.get(at).is_some_and(|b| !b.is_ascii_whitespace()) {
+            at += 1;
+        }
+
+        if start == at {
+            return Err(invalid("the header stops short"));
+        }
+
+        fields.push(String::from_utf8_lossy(&bytes[start..at]).into_owned());
+    }
+
+    let channels = match fields[0].as_str() {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(invalid("it doesn't start with PF or Pf"))
+    };
+
+    let size = |field: &str| field.parse::<usize>().ok().filter(|&n| n > 0);
+    let (Some(width), Some(height)) = (size(&fields[1]), size(&fields[2])) else {
+        return Err(invalid("the size isn't two positive numbers"));
+    };
+
+    let scale: f32 = fields[3].parse().map_err(|_| invalid("the scale isn't a number"))?;
+
+    let data = bytes.get(at + 1..).unwrap_or_default();
+    let length = width.checked_mul(height).and_then(|n| n.checked_mul(channels * 4)).filter(|&n| n <= data.len())
+        .ok_or_else(|| invalid("there are fewer pixels than the size says"))?;
+
+    let values: Vec<f32> = data[..length].chunks_exact(4).map(|b| {
+        let b = [b[0], b[1], b[2], b[3]];
+        // a negative scale marks the data as little-endian
+        if scale < 0.0 { f32::from_le_bytes(b) } else { f32::from_be_bytes(b) }
+    }).collect();
+
+    let pixels: Vec<[f32; 3]> = values.chunks_exact(channels).map(|p| if channels == 3 { [p[0], p[1], p[2]] } else { [p[0]; 3] }).collect();
+
+    Ok((width, height, pixels.chunks(width).rev().flatten().copied().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, fs::File, io::BufReader, path::Path};
+
+    use image::{codecs::hdr::HdrDecoder, Rgb};
+
+    use crate::structs::{hdr_output::{save_float, is_float_format, load_pfm}, framebuffer::Framebuffer, color::Color};
+
+    #[test]
+    fn keeps_radiance() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(0, 0, Color::new(4.0, 0.5, 0.0), 1);
+        framebuffer.set(2, 1, Color::new(0.25, 16.0, 1.0), 1);
+
+        let dir = env::temp_dir();
+
+        for ext in ["hdr", "exr", "pfm"] {
+            let path = dir.join(format!("eyebeam_keeps_radiance.{}", ext));
+            assert!(is_float_format(&path));
+            save_float(&framebuffer, &path).unwrap();
+
+            let pixels: Vec<Rgb<f32>> = match ext {
+                // image's own loader turns RGBE into 8-bit, so go through the decoder to get the floats back
+                "hdr" => HdrDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap().read_image_hdr().unwrap(),
+                "pfm" => load_pfm(&path).unwrap().2.into_iter().map(Rgb).collect(),
+                _ => image::open(&path).unwrap().into_rgb32f().pixels().copied().collect()
+            };
+
+            let [r, g, _] = pixels[0].0;
+            let [_, g2, _] = pixels[5].0;
+
+            // RGBE only keeps 8 bits of mantissa
+            assert!((r - 4.0).abs() < 0.05 && (g - 0.5).abs() < 0.01, "{} {:?}", ext, pixels[0]);
+            assert!((g2 - 16.0).abs() < 0.1, "{} {:?}", ext, pixels[5]);
+
+            fs::remove_file(&path).unwrap();
+        }
+
+        assert!(!is_float_format(Path::new("img.png")));
+    }
+
+    #[test]
+    fn reads_float_maps() {
+        let path = env::temp_dir().join("eyebeam_reads_float_maps.pfm");
+
+        // greyscale and big-endian, as other programs might write it, with the bottom row first
+        let mut grey = b"Pf\n2 2\n1.0\n".to_vec();
+        grey.extend([1.0f32, 2.0, 3.0, 4.0].iter().flat_map(|v| v.to_be_bytes()));
+        fs::write(&path, &grey).unwrap();
+        assert_eq!(load_pfm(&path).unwrap(), (2, 2, vec![[3.0; 3], [4.0; 3], [1.0; 3], [2.0; 3]]));
+
+        // and a header that promises more than is there
+        fs::write(&path, &grey[..grey.len() - 1]).unwrap();
+        assert!(load_pfm(&path).unwrap_err().to_string().contains("fewer pixels"));
+
+        fs::write(&path, b"P6\n2 2\n255\n").unwrap();
+        assert!(load_pfm(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
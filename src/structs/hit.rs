@@ -0,0 +1,84 @@
+use super::{vec3::Vec3, ray::Ray, appearance::Appearance};
+
+/// Everything known about a single ray/surface intersection.
+#[derive(Clone, Copy)]
+pub struct Hit<'a> {
+    pub distance: f64,
+    pub point: Vec3,
+    // Outward-facing normal of the actual geometry
+    pub normal: Vec3,
+    // Normal used for lighting; the same as `normal` unless the shape smooths it (e.g. mesh vertex normals)
+    pub shading_normal: Vec3,
+    // Whether the ray arrived from the side `normal` points to
+    pub front_face: bool,
+    pub uv: (f64, f64),
+    // Which part of the shape was hit (triangle index for meshes, face index for prisms)
+    pub primitive: usize,
+    pub appearance: &'a Appearance
+}
+
+impl<'a> Hit<'a> {
+    pub fn new(ray: &Ray, distance: f64, normal: Vec3, appearance: &'a Appearance) -> Self {
+        Self {
+            distance,
+            point: ray.origin + ray.direction * distance,
+            normal,
+            shading_normal: normal,
+            front_face: ray.direction.dot(&normal) < 0.0,
+            uv: (0.0, 0.0),
+            primitive: 0,
+            appearance
+        }
+    }
+
+    pub fn with_uv(self, u: f64, v: f64) -> Self {
+        Self { uv: (u, v), ..self }
+    }
+
+    pub fn with_shading_normal(self, shading_normal: Vec3) -> Self {
+        Self { shading_normal, ..self }
+    }
+
+    pub fn with_primitive(self, primitive: usize) -> Self {
+        Self { primitive, ..self }
+    }
+
+    /// The shading normal, flipped if need be so that it points back towards where the ray came from.
+    pub fn facing_normal(&self) -> Vec3 {
+        if self.front_face { self.shading_normal } else { self.shading_normal.invert() }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{sphere::Sphere, plane::Plane, shape::Shape, appearance::Appearance, finish::Finish, color::Color, ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn hits_know_which_side_they_are_on() {
+        let sphere = Sphere::new(Vec3::new(0, 0, 5), 1.0, Appearance::new(Color::WHITE, Finish::DEFAULT));
+
+        let outside = sphere.closest_hit(&Ray::new(Vec3::O, Vec3::K)).unwrap();
+        assert!(outside.front_face);
+        assert_eq!((outside.normal, outside.facing_normal()), (Vec3::new(0, 0, -1), Vec3::new(0, 0, -1)));
+
+        // from the centre, the surface is seen from behind and the normal gets turned around
+        let inside = sphere.closest_hit(&Ray::new(Vec3::new(0, 0, 5), Vec3::K)).unwrap();
+        assert!(!inside.front_face);
+        assert_eq!((inside.normal, inside.facing_normal()), (Vec3::K, Vec3::new(0, 0, -1)));
+    }
+
+    #[test]
+    fn shapes_map_their_surfaces() {
+        let appearance = Appearance::new(Color::WHITE, Finish::DEFAULT);
+
+        // a sphere's u goes around its equator, and v from pole to pole
+        let sphere = Sphere::new(Vec3::new(0, 0, 5), 1.0, appearance.clone());
+        let (u, v) = sphere.closest_hit(&Ray::new(Vec3::O, Vec3::K)).unwrap().uv;
+        assert!((u - 0.25).abs() < 1e-12 && (v - 0.5).abs() < 1e-12, "{:?}", (u, v));
+
+        // a plane's are distances from its point, along two directions in it
+        let plane = Plane::new(Vec3::new(0, 5, 0), Vec3::new(0, -1, 0), appearance);
+        let hit = plane.closest_hit(&Ray::new(Vec3::new(2, 0, 3), Vec3::J)).unwrap();
+        assert_eq!((hit.uv, hit.primitive), ((-3.0, 2.0), 0));
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use super::{shape::Shape, mesh::MeshAsset, matrix::Transform, appearance::Appearance, ray::Ray, hit::Hit, bvh::Aabb, transformed::hit_to_world};
+
+/// One placement of a shared mesh: the geometry (and its BVH) lives in the `MeshAsset`,
+/// while each instance only carries its own transform and appearance.
+pub struct MeshInstance {
+    pub asset: Arc<MeshAsset>,
+    pub transform: Transform,
+    pub appearance: Appearance
+}
+
+impl MeshInstance {
+    pub fn new(asset: Arc<MeshAsset>, transform: Transform, appearance: Appearance) -> Self {
+        Self {
+            asset,
+            transform,
+            appearance
+        }
+    }
+}
+
+impl Shape for MeshInstance {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
+        let local = self.transform.ray_to_object(ray);
+
+        self.asset.intersections(&local, &self.appearance).into_iter()
+            .map(|hit| hit_to_world(&self.transform, ray, hit))
+            .collect()
+    }
+
+    fn closest_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        let local = self.transform.ray_to_object(ray);
+
+        self.asset.closest_hit(&local, &self.appearance).map(|hit| hit_to_world(&self.transform, ray, hit))
+    }
+
+    // see `ColoredMesh::bounds`: an empty box doesn't survive being transformed
+    fn bounds(&self) -> Option<Aabb> {
+        if self.asset.triangles().is_empty() {
+            return None;
+        }
+
+        Some(self.transform.bounds_to_world(&self.asset.bounds()))
+    }
+
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        Some(&mut self.transform)
+    }
+
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        Some(&mut self.appearance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::structs::{instance::MeshInstance, mesh::{ColoredMesh, MeshAsset}, matrix::Transform, appearance::Appearance, color::Color, finish::Finish, shape::Shape, vec3::Vec3};
+
+    #[test]
+    fn empty_meshes_have_no_bounds() {
+        let asset = Arc::new(MeshAsset::from_mesh(tobj::Mesh::default()));
+        let appearance = Appearance::new(Color::WHITE, Finish::DEFAULT);
+
+        let instance = MeshInstance::new(asset.clone(), Transform::compose(Vec3::new(2, 2, 2), Vec3::O, Vec3::I), appearance.clone());
+        let colored = ColoredMesh { asset, location: Vec3::I, appearance };
+
+        assert!(instance.bounds().is_none() && colored.bounds().is_none());
+    }
+}
@@ -0,0 +1,177 @@
+use std::str::FromStr;
+
+use super::{vec3::Vec3, color::Color};
+
+/// How a track gets from one key to the next. Each key's interpolation covers the stretch
+/// after it, so the last key's never gets used.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Interpolation {
+    // holds the value until the next key, then jumps
+    Step,
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+    // a timing curve like CSS's cubic-bezier(x1, y1, x2, y2), from (0, 0) to (1, 1); the x's must be within 0 to 1,
+    // while y's outside it overshoot
+    Bezier(f64, f64, f64, f64)
+}
+
+impl FromStr for Interpolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "step" => Ok(Interpolation::Step),
+            "linear" => Ok(Interpolation::Linear),
+            "ease-in" => Ok(Interpolation::EaseIn),
+            "ease-out" => Ok(Interpolation::EaseOut),
+            "ease-in-out" => Ok(Interpolation::EaseInOut),
+            other => Err(format!("Unknown interpolation `{}` (expected step, linear, ease-in, ease-out, ease-in-out or a bezier)", other))
+        }
+    }
+}
+
+impl Interpolation {
+    /// How far along to be, from 0 to 1 (give or take a Bezier's overshoot), `t` of the way between two keys.
+    pub fn ease(&self, t: f64) -> f64 {
+        match *self {
+            Interpolation::Step => 0.0,
+            Interpolation::Linear => t,
+            // the same curves CSS uses for its named timing functions
+            Interpolation::EaseIn => Interpolation::Bezier(0.42, 0.0, 1.0, 1.0).ease(t),
+            Interpolation::EaseOut => Interpolation::Bezier(0.0, 0.0, 0.58, 1.0).ease(t),
+            Interpolation::EaseInOut => Interpolation::Bezier(0.42, 0.0, 0.58, 1.0).ease(t),
+            Interpolation::Bezier(x1, y1, x2, y2) => {
+                let curve = |a: f64, b: f64, s: f64| 3.0 * a * s * (1.0 - s).powi(2) + 3.0 * b * s * s * (1.0 - s) + s.powi(3);
+
+                // x only ever goes up while the control points' x's stay within 0 to 1, so the s giving x = t can be bisected for
+                let (mut low, mut high) = (0.0, 1.0);
+
+                for _ in 0..50 {
+                    let mid = (low + high) / 2.0;
+                    if curve(x1, x2, mid) < t { low = mid } else { high = mid }
+                }
+
+                curve(y1, y2, (low + high) / 2.0)
+            }
+        }
+    }
+}
+
+/// Values that can be blended between keys.
+pub trait Interpolate: Copy {
+    /// `self` at 0 and `other` at 1, in a straight line.
+    fn lerp(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for Vec3 {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        *self + Vec3::between(self, other) * t
+    }
+}
+
+// in linear light, so a fade between two colours doesn't dip in brightness on the way
+impl Interpolate for Color {
+    fn lerp(&self, other: &Self, t: f64) -> Self {
+        Color::new(self.r.lerp(&other.r, t), self.g.lerp(&other.g, t), self.b.lerp(&other.b, t))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Keyframe<T> {
+    pub time: f64,
+    pub value: T,
+    pub interpolation: Interpolation
+}
+
+/// A value that changes over time, given by its keyframes. Before the first key it has the first
+/// key's value, and after the last it keeps the last's.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Track<T> {
+    keys: Vec<Keyframe<T>>
+}
+
+impl<T> Default for Track<T> {
+    fn default() -> Self {
+        Self { keys: Vec::new() }
+    }
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A track that never changes.
+    pub fn constant(value: T) -> Self {
+        Track::new().key(0.0, value, Interpolation::Step)
+    }
+
+    /// Adds a key at `time` seconds. Keys can come in any order; two at the same time make an instant jump.
+    pub fn key(mut self, time: f64, value: T, interpolation: Interpolation) -> Self {
+        let index = self.keys.partition_point(|key| key.time <= time);
+        self.keys.insert(index, Keyframe { time, value, interpolation });
+        self
+    }
+
+    pub fn keys(&self) -> &[Keyframe<T>] {
+        &self.keys
+    }
+
+    /// The value at `time` seconds; None if there are no keys at all.
+    pub fn value_at(&self, time: f64) -> Option<T> {
+        let next = self.keys.partition_point(|key| key.time <= time);
+
+        match (next.checked_sub(1).map(|i| &self.keys[i]), self.keys.get(next)) {
+            (Some(from), Some(to)) => {
+                let t = (time - from.time) / (to.time - from.time);
+                Some(from.value.lerp(&to.value, from.interpolation.ease(t)))
+            },
+            (Some(last), None) => Some(last.value),
+            (None, first) => first.map(|key| key.value)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{keyframe::{Interpolation, Track}, vec3::Vec3};
+
+    #[test]
+    fn interpolation() {
+        let track = Track::new()
+            .key(2.0, 10.0, Interpolation::Step)
+            .key(0.0, 0.0, Interpolation::Linear)
+            .key(1.0, 4.0, Interpolation::EaseInOut)
+            .key(3.0, 20.0, Interpolation::Linear);
+
+        // keys get sorted, and the ends hold
+        assert_eq!(track.value_at(-1.0), Some(0.0));
+        assert_eq!(track.value_at(0.25), Some(1.0));
+        assert_eq!(track.value_at(10.0), Some(20.0));
+
+        // easing is symmetrical about the middle, and slow at the ends
+        assert!((track.value_at(1.5).unwrap() - 7.0).abs() < 1e-9);
+        assert!(track.value_at(1.1).unwrap() < 4.6);
+
+        // a step waits for the next key
+        assert_eq!(track.value_at(2.99), Some(10.0));
+        assert_eq!(track.value_at(3.0), Some(20.0));
+
+        // the named eases are just Beziers, and a straight Bezier is linear
+        assert_eq!(Interpolation::EaseIn.ease(0.3), Interpolation::Bezier(0.42, 0.0, 1.0, 1.0).ease(0.3));
+        assert!((Interpolation::Bezier(0.25, 0.25, 0.75, 0.75).ease(0.3) - 0.3).abs() < 1e-9);
+        assert!(Interpolation::Bezier(0.3, 1.5, 0.7, 1.5).ease(0.5) > 1.0);
+
+        let moves = Track::constant(Vec3::I);
+        assert_eq!(moves.value_at(5.0), Some(Vec3::I));
+        assert_eq!(Track::<f64>::new().value_at(0.0), None);
+    }
+}
@@ -1,17 +1,17 @@
-use image::Rgba;
-use super::{vec3::Vec3, color::{color_multiply, color_scale}};
+use super::{vec3::Vec3, color::Color};
 
 pub struct Light {
     pub position: Vec3,
-    pub color: Rgba<u8>
+    // not limited to 1.0; brighter lights just have bigger channels
+    pub color: Color
 }
 
 impl Light {
-    pub fn new(position: Vec3, color: Rgba<u8>) -> Self {
+    pub fn new(position: Vec3, color: Color) -> Self {
         Self {position, color}
     }
 
-    pub fn illuminate(&self, appearance: Rgba<u8>, _point: Vec3, brightness: f64) -> Rgba<u8> {
-        color_scale(&color_multiply(&appearance, &self.color.clone()), brightness)
+    pub fn illuminate(&self, appearance: Color, _point: Vec3, brightness: f64) -> Color {
+        appearance * self.color * brightness
     }
 }
\ No newline at end of file
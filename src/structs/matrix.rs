@@ -0,0 +1,247 @@
+use std::ops::Mul;
+
+use super::{vec3::Vec3, ray::Ray, bvh::Aabb};
+
+/// Row-major 4x4 matrix for affine transforms. Points are treated as (x, y, z, 1) and vectors as (x, y, z, 0).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4 {
+    pub m: [[f64; 4]; 4]
+}
+
+impl Mul for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        let mut m = [[0.0; 4]; 4];
+
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, cell) in out.iter_mut().enumerate() {
+                *cell = (0..4).map(|k| self.m[row][k] * rhs.m[k][col]).sum();
+            }
+        }
+
+        Matrix4 { m }
+    }
+}
+
+impl Matrix4 {
+    pub const IDENTITY: Matrix4 = Matrix4 { m: [
+        [1.0, 0.0, 0.0, 0.0],
+        [0.0, 1.0, 0.0, 0.0],
+        [0.0, 0.0, 1.0, 0.0],
+        [0.0, 0.0, 0.0, 1.0]
+    ]};
+
+    pub fn translation(offset: Vec3) -> Self {
+        let mut m = Self::IDENTITY.m;
+        m[0][3] = offset.x;
+        m[1][3] = offset.y;
+        m[2][3] = offset.z;
+        Matrix4 { m }
+    }
+
+    pub fn scaling(factors: Vec3) -> Self {
+        let mut m = Self::IDENTITY.m;
+        m[0][0] = factors.x;
+        m[1][1] = factors.y;
+        m[2][2] = factors.z;
+        Matrix4 { m }
+    }
+
+    /// Rotation by `degrees` around `axis` (right-handed, Rodrigues' formula).
+    pub fn rotation(axis: Vec3, degrees: f64) -> Self {
+        let Vec3 { x, y, z } = axis.unit();
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let t = 1.0 - cos;
+
+        Matrix4 { m: [
+            [t * x * x + cos,     t * x * y - sin * z, t * x * z + sin * y, 0.0],
+            [t * x * y + sin * z, t * y * y + cos,     t * y * z - sin * x, 0.0],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos,     0.0],
+            [0.0,                 0.0,                 0.0,                 1.0]
+        ]}
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut m = [[0.0; 4]; 4];
+
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, cell) in out.iter_mut().enumerate() {
+                *cell = self.m[col][row];
+            }
+        }
+
+        Matrix4 { m }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting. None if the matrix is singular (e.g. a zero scale).
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.m;
+        let mut inv = Self::IDENTITY.m;
+
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for k in 0..4 {
+                a[col][k] *= scale;
+                inv[col][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == col { continue; }
+
+                let factor = a[row][col];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[col][k];
+                    inv[row][k] -= factor * inv[col][k];
+                }
+            }
+        }
+
+        Some(Matrix4 { m: inv })
+    }
+
+    pub fn transform_point(&self, point: &Vec3) -> Vec3 {
+        self.transform_vector(point) + Vec3::new(self.m[0][3], self.m[1][3], self.m[2][3])
+    }
+
+    pub fn transform_vector(&self, vector: &Vec3) -> Vec3 {
+        let m = &self.m;
+
+        Vec3::new(
+            m[0][0] * vector.x + m[0][1] * vector.y + m[0][2] * vector.z,
+            m[1][0] * vector.x + m[1][1] * vector.y + m[1][2] * vector.z,
+            m[2][0] * vector.x + m[2][1] * vector.y + m[2][2] * vector.z
+        )
+    }
+}
+
+/// An affine transform together with its inverse, which is what actually gets used
+/// when taking rays into object space.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform {
+    pub matrix: Matrix4,
+    pub inverse: Matrix4
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform { matrix: Matrix4::IDENTITY, inverse: Matrix4::IDENTITY };
+
+    pub fn new(matrix: Matrix4) -> Option<Self> {
+        matrix.inverse().map(|inverse| Transform { matrix, inverse })
+    }
+
+    // The builder methods below apply their step *after* everything already in the transform,
+    // so `Transform::IDENTITY.scale(..).rotate(..).translate(..)` reads in the order it happens.
+
+    pub fn then(&self, other: &Transform) -> Self {
+        Transform {
+            matrix: other.matrix * self.matrix,
+            inverse: self.inverse * other.inverse
+        }
+    }
+
+    pub fn translate(&self, offset: Vec3) -> Self {
+        self.then(&Transform { matrix: Matrix4::translation(offset), inverse: Matrix4::translation(offset.invert()) })
+    }
+
+    /// Non-uniform scale. Zero factors are not invertible, and leave the inverse full of infinities;
+    /// scene files refuse them.
+    pub fn scale(&self, factors: Vec3) -> Self {
+        let inverse = Vec3::new(1.0 / factors.x, 1.0 / factors.y, 1.0 / factors.z);
+        self.then(&Transform { matrix: Matrix4::scaling(factors), inverse: Matrix4::scaling(inverse) })
+    }
+
+    pub fn rotate(&self, axis: Vec3, degrees: f64) -> Self {
+        // rotation matrices are orthogonal, so the inverse is just the transpose
+        let rotation = Matrix4::rotation(axis, degrees);
+        self.then(&Transform { matrix: rotation, inverse: rotation.transpose() })
+    }
+
+    /// Scales, then rotates about x, then y, then z (in degrees), then translates, the way scene files give transforms.
+    pub fn compose(scale: Vec3, degrees: Vec3, offset: Vec3) -> Self {
+        Transform::IDENTITY
+            .scale(scale)
+            .rotate(Vec3::I, degrees.x).rotate(Vec3::J, degrees.y).rotate(Vec3::K, degrees.z)
+            .translate(offset)
+    }
+
+    pub fn point_to_world(&self, point: &Vec3) -> Vec3 {
+        self.matrix.transform_point(point)
+    }
+
+    pub fn point_to_object(&self, point: &Vec3) -> Vec3 {
+        self.inverse.transform_point(point)
+    }
+
+    /// Normals don't transform like vectors under non-uniform scale; they need the inverse transpose.
+    pub fn normal_to_world(&self, normal: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(normal).unit()
+    }
+
+    pub fn ray_to_object(&self, ray: &Ray) -> Ray {
+        Ray::new(self.inverse.transform_point(&ray.origin), self.inverse.transform_vector(&ray.direction))
+    }
+
+    /// World-space box around the transformed corners of an object-space box.
+    pub fn bounds_to_world(&self, bounds: &Aabb) -> Aabb {
+        let corners: Vec<Vec3> = (0..8).map(|i| Vec3::new(
+            if i & 1 == 0 { bounds.min.x } else { bounds.max.x },
+            if i & 2 == 0 { bounds.min.y } else { bounds.max.y },
+            if i & 4 == 0 { bounds.min.z } else { bounds.max.z }
+        )).map(|corner| self.point_to_world(&corner)).collect();
+
+        Aabb::from_points(&corners)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{matrix::{Matrix4, Transform}, vec3::Vec3};
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn inverse() {
+        let m = Matrix4::translation(Vec3::new(1, 2, 3)) * Matrix4::rotation(Vec3::new(1, 1, 0), 30.0) * Matrix4::scaling(Vec3::new(2, 3, 4));
+        let product = m * m.inverse().unwrap();
+
+        for row in 0..4 {
+            for col in 0..4 {
+                let expected = if row == col { 1.0 } else { 0.0 };
+                assert!((product.m[row][col] - expected).abs() < 1e-9);
+            }
+        }
+
+        assert!(Matrix4::scaling(Vec3::new(1, 0, 1)).inverse().is_none());
+    }
+
+    #[test]
+    fn transform_order() {
+        // scale first, then rotate a quarter turn about +y, then move
+        let transform = Transform::IDENTITY
+            .scale(Vec3::new(2, 2, 2))
+            .rotate(Vec3::J, 90.0)
+            .translate(Vec3::new(0, 0, 10));
+
+        let world = transform.point_to_world(&Vec3::I);
+        assert!(close(world, Vec3::new(0, 0, 8)), "{:?}", world);
+        assert!(close(transform.point_to_object(&world), Vec3::I));
+
+        // a normal on a plane squashed along x stays perpendicular to it
+        let squash = Transform::IDENTITY.scale(Vec3::new(0.5, 1.0, 1.0));
+        let normal = squash.normal_to_world(&Vec3::new(1, 1, 0).unit());
+        let along_surface = squash.matrix.transform_vector(&Vec3::new(1, -1, 0));
+        assert!(normal.dot(&along_surface).abs() < 1e-9);
+    }
+}
@@ -1,64 +1,110 @@
+use std::{path::Path, sync::Arc};
+
 use tobj::Mesh;
 
-use super::{shape::Shape, appearance::Appearance, vec3::Vec3, ray::Ray};
+use super::{error::{Error, Result}, shape::{Shape, SELF_HIT_THRESHOLD}, appearance::Appearance, vec3::Vec3, ray::Ray, bvh::{Aabb, Bvh}, hit::Hit};
 
 type Triangle = [Vec3; 3];
 
-pub struct ColoredMesh {
+/// Geometry loaded from an OBJ file, along with the BVH over its triangles. Everything in here is
+/// in the mesh's own coordinates; the shapes that draw it (`ColoredMesh`, `MeshInstance`) decide where it goes.
+/// Wrapped in an `Arc` so any number of shapes can share one copy.
+pub struct MeshAsset {
     pub mesh: Mesh,
-    pub location: Vec3,
-    pub appearance: Appearance
+    triangles: Vec<Triangle>,
+    bvh: Bvh
 }
 
-impl Shape for ColoredMesh {
-    fn intersections(&self, ray: &Ray) -> Vec<f64> {
-        let mut res: Vec<f64> = Vec::new();
+impl MeshAsset {
+    pub fn load(path: &Path) -> Result<Arc<Self>> {
+        let (models, _materials) = tobj::load_obj(path, &tobj::GPU_LOAD_OPTIONS).map_err(|source| Error::Mesh { path: path.to_path_buf(), source })?;
+        let mesh = models.into_iter().nth(0).ok_or_else(|| Error::EmptyMesh { path: path.to_path_buf() })?.mesh;
 
-        for tri in self.triangles() {
-            if let Some((_intersection_point, scalar)) = ColoredMesh::intersect_triangle(ray, &tri) {
-                res.push(scalar);
-            }
+        Ok(Arc::new(MeshAsset::from_mesh(mesh)))
+    }
+
+    pub fn from_mesh(mesh: Mesh) -> Self {
+        let triangles = MeshAsset::build_triangles(&mesh);
+        let bounds: Vec<Aabb> = triangles.iter().map(|tri| Aabb::from_points(tri)).collect();
+        let bvh = Bvh::build(&bounds);
+
+        Self {
+            mesh,
+            triangles,
+            bvh
         }
+    }
 
-        res
+    pub fn triangles(&self) -> &[Triangle] {
+        &self.triangles
     }
 
-    fn normal_at(&self, _point: &Vec3, ray: &Ray) -> Vec3 {
-        // Get the normal of the (closest) triangle
-        let triangle: Triangle = 
-            self.triangles()
-                .into_iter()
-                .filter(|tri| ColoredMesh::intersect_triangle(ray, tri).is_some())    // Only grab tris that intersect the ray
-                .reduce(|best, cur| {                                       // Find the tri with the vertex closest to the ray's origin
-                    // If any of the current tri's vertexes are closer than all of the known closest tri's vertexes...
-                    if cur.iter().any(|cur_vert| best.iter().all(|best_vert| Vec3::between(&ray.origin, cur_vert).length() < Vec3::between(&ray.origin, best_vert).length())) {
-                        cur
-                    } else {
-                        best
-                    }
-                }).expect("Trying to get a normal on the mesh, but the ray doesn't intersect?");
+    pub fn bounds(&self) -> Aabb {
+        self.bvh.bounds()
+    }
+
+    pub fn intersections<'a>(&self, ray: &Ray, appearance: &'a Appearance) -> Vec<Hit<'a>> {
+        let mut res: Vec<Hit> = Vec::new();
+
+        self.bvh.visit(ray, |i| {
+            if let Some(barycentric) = MeshAsset::intersect_triangle(ray, &self.triangles[i]) {
+                res.push(self.hit_at(ray, i, barycentric, appearance));
+            }
+        });
 
-        Vec3::between(&triangle[0], &triangle[1]).cross(&Vec3::between(&triangle[1], &triangle[2])).unit()
+        res
     }
 
-    fn appearance(&self) -> Appearance {
-        self.appearance.clone()
+    pub fn closest_hit<'a>(&self, ray: &Ray, appearance: &'a Appearance) -> Option<Hit<'a>> {
+        let mut closest: Option<(usize, (f64, f64, f64))> = None;
+
+        self.bvh.nearest(ray, |i| {
+            let barycentric = MeshAsset::intersect_triangle(ray, &self.triangles[i]).filter(|&(t, _, _)| t > SELF_HIT_THRESHOLD)?;
+
+            if closest.is_none_or(|(_, (best, _, _))| barycentric.0 < best) {
+                closest = Some((i, barycentric));
+            }
+
+            Some(barycentric.0)
+        });
+
+        closest.map(|(i, barycentric)| self.hit_at(ray, i, barycentric, appearance))
     }
-}
 
-impl ColoredMesh {
-    pub fn new(mesh_url: &str, location: Vec3, appearance: Appearance) -> Self {
-        let (models, _materials) = tobj::load_obj(mesh_url, &tobj::GPU_LOAD_OPTIONS).unwrap_or_else(|_| panic!("Unable to load object file {}", mesh_url));
-        let mesh = models.into_iter().nth(0).unwrap_or_else(|| panic!("No models defined in {}", mesh_url)).mesh;
+    fn hit_at<'a>(&self, ray: &Ray, index: usize, (t, u, v): (f64, f64, f64), appearance: &'a Appearance) -> Hit<'a> {
+        let [a, b, c] = &self.triangles[index];
+        let normal = Vec3::between(a, b).cross(&Vec3::between(b, c)).unit();
+        let mut hit = Hit::new(ray, t, normal, appearance).with_primitive(index);
 
-        Self {
-            mesh,
-            location,
-            appearance
+        // mesh.indices are shared between positions, normals and texcoords (GPU_LOAD_OPTIONS sets single_index)
+        let corners = &self.mesh.indices[index * 3..index * 3 + 3];
+        let weights = [1.0 - u - v, u, v];
+
+        if !self.mesh.normals.is_empty() {
+            let smooth = corners.iter().zip(weights).fold(Vec3::O, |acc, (&vert, w)| {
+                acc + <&[f32] as Into<Vec3>>::into(&self.mesh.normals[(vert as usize) * 3..(vert as usize + 1) * 3]) * w
+            });
+
+            if smooth.length() > f64::EPSILON {
+                hit = hit.with_shading_normal(smooth.unit());
+            }
+        }
+
+        if self.mesh.texcoords.is_empty() {
+            hit.with_uv(u, v)
+        } else {
+            let (tex_u, tex_v) = corners.iter().zip(weights).fold((0.0, 0.0), |(acc_u, acc_v), (&vert, w)| {
+                let vert = vert as usize;
+                (acc_u + self.mesh.texcoords[vert * 2] as f64 * w, acc_v + self.mesh.texcoords[vert * 2 + 1] as f64 * w)
+            });
+
+            hit.with_uv(tex_u, tex_v)
         }
     }
 
-    pub fn intersect_triangle(ray: &Ray, tri: &Triangle) -> Option<(Vec3, f64)>
+    /// Möller–Trumbore. Returns the distance along the ray and the barycentric (u, v) of the hit.
+    /// Both sides of the triangle count; which one was hit ends up in `Hit::front_face`.
+    pub fn intersect_triangle(ray: &Ray, tri: &Triangle) -> Option<(f64, f64, f64)>
     {
         let [a,b,c] = tri;
 
@@ -76,26 +122,125 @@ impl ColoredMesh {
         let v = -1.0 * e1.dot(&dao) * invdet;
         let t = ao.dot(&n) * invdet;
 
-        if det >= 1e-6 && t >= 0.0 && u >= 0.0 && v >= 0.0 && (u+v) <= 1.0 {
-            Some((ray.origin + ray.direction * t, t))
+        if det.abs() >= 1e-12 && t >= 0.0 && u >= 0.0 && v >= 0.0 && (u+v) <= 1.0 {
+            Some((t, u, v))
         } else {
             None
         }
     }
 
-    pub fn triangles(&self) -> Vec<Triangle> {
+    fn build_triangles(mesh: &Mesh) -> Vec<Triangle> {
         let mut res: Vec<Triangle> = Vec::new();
 
         // mesh.indices is a *flattened* vector of indices into mesh.positions
-        for idx in self.mesh.indices.as_slice().chunks(3) {
+        for idx in mesh.indices.as_slice().chunks(3) {
             let [vert_index_a, vert_index_b, vert_index_c] = [idx[0], idx[1], idx[2]];
             res.push([
-                <&[f32] as Into<Vec3>>::into(&self.mesh.positions[(vert_index_a as usize) * 3..(vert_index_a as usize + 1)*3]) + self.location,
-                <&[f32] as Into<Vec3>>::into(&self.mesh.positions[(vert_index_b as usize) * 3..(vert_index_b as usize + 1)*3]) + self.location,
-                <&[f32] as Into<Vec3>>::into(&self.mesh.positions[(vert_index_c as usize) * 3..(vert_index_c as usize + 1)*3]) + self.location
+                <&[f32] as Into<Vec3>>::into(&mesh.positions[(vert_index_a as usize) * 3..(vert_index_a as usize + 1)*3]),
+                <&[f32] as Into<Vec3>>::into(&mesh.positions[(vert_index_b as usize) * 3..(vert_index_b as usize + 1)*3]),
+                <&[f32] as Into<Vec3>>::into(&mesh.positions[(vert_index_c as usize) * 3..(vert_index_c as usize + 1)*3])
             ]);
         }
 
         res
     }
-}
\ No newline at end of file
+}
+
+/// A mesh placed in the scene by a plain offset.
+pub struct ColoredMesh {
+    pub asset: Arc<MeshAsset>,
+    pub location: Vec3,
+    pub appearance: Appearance
+}
+
+impl Shape for ColoredMesh {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
+        self.asset.intersections(&self.to_local(ray), &self.appearance).into_iter()
+            .map(|hit| self.to_world(ray, hit))
+            .collect()
+    }
+
+    fn closest_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.asset.closest_hit(&self.to_local(ray), &self.appearance).map(|hit| self.to_world(ray, hit))
+    }
+
+    // a mesh without triangles has an empty box, which would come out of the offset as infinities
+    fn bounds(&self) -> Option<Aabb> {
+        if self.asset.triangles().is_empty() {
+            return None;
+        }
+
+        let bounds = self.asset.bounds();
+        Some(Aabb::new(bounds.min + self.location, bounds.max + self.location))
+    }
+
+    fn location_mut(&mut self) -> Option<&mut Vec3> {
+        Some(&mut self.location)
+    }
+
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        Some(&mut self.appearance)
+    }
+}
+
+impl ColoredMesh {
+    pub fn new(path: &Path, location: Vec3, appearance: Appearance) -> Result<Self> {
+        Ok(ColoredMesh::from_asset(MeshAsset::load(path)?, location, appearance))
+    }
+
+    pub fn from_asset(asset: Arc<MeshAsset>, location: Vec3, appearance: Appearance) -> Self {
+        Self {
+            asset,
+            location,
+            appearance
+        }
+    }
+
+    // a pure translation leaves distances along the ray untouched, so only the point needs moving
+    fn to_local(&self, ray: &Ray) -> Ray {
+        Ray { origin: ray.origin - self.location, direction: ray.direction }
+    }
+
+    fn to_world<'a>(&self, ray: &Ray, hit: Hit<'a>) -> Hit<'a> {
+        Hit { point: ray.origin + ray.direction * hit.distance, ..hit }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::structs::{mesh::{ColoredMesh, MeshAsset}, shape::Shape, appearance::Appearance, finish::Finish, color::Color, ray::Ray, vec3::Vec3};
+
+    // two triangles side by side in the z = 0 plane, both wound to face +z
+    fn pair() -> ColoredMesh {
+        let mesh = tobj::Mesh {
+            positions: vec![0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 2.0, 0.0, 0.0, 3.0, 0.0, 0.0, 2.0, 1.0, 0.0],
+            indices: vec![0, 1, 2, 3, 4, 5],
+            ..Default::default()
+        };
+
+        ColoredMesh { asset: Arc::new(MeshAsset::from_mesh(mesh)), location: Vec3::O, appearance: Appearance::new(Color::WHITE, Finish::DEFAULT) }
+    }
+
+    #[test]
+    fn triangles_are_double_sided() {
+        let mesh = pair();
+
+        let front = mesh.closest_hit(&Ray::new(Vec3::new(0.25, 0.5, 1.0), Vec3::new(0, 0, -1))).unwrap();
+        let back = mesh.closest_hit(&Ray::new(Vec3::new(0.25, 0.5, -1.0), Vec3::K)).unwrap();
+
+        assert!(front.front_face && !back.front_face);
+        assert_eq!((front.normal, back.normal), (Vec3::K, Vec3::K));
+        assert_eq!(back.facing_normal(), Vec3::new(0, 0, -1));
+
+        // without texture coordinates, uv are the barycentric weights of the second and third corners
+        assert!((front.uv.0 - 0.25).abs() < 1e-12 && (front.uv.1 - 0.5).abs() < 1e-12, "{:?}", front.uv);
+
+        let second = mesh.closest_hit(&Ray::new(Vec3::new(2.25, 0.25, 1.0), Vec3::new(0, 0, -1))).unwrap();
+        assert_eq!((front.primitive, second.primitive), (0, 1));
+
+        // and rays in the triangles' own plane go past
+        assert!(mesh.closest_hit(&Ray::new(Vec3::new(-1.0, 0.25, 0.0), Vec3::I)).is_none());
+    }
+}
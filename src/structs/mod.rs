@@ -12,4 +12,23 @@ pub mod util;
 pub mod finish;
 pub mod appearance;
 pub mod animate;
-pub mod mesh;
\ No newline at end of file
+pub mod mesh;
+pub mod scene_file;
+pub mod bvh;
+pub mod hit;
+pub mod matrix;
+pub mod transformed;
+pub mod instance;
+pub mod csg;
+pub mod tonemap;
+pub mod framebuffer;
+pub mod hdr_output;
+pub mod sampler;
+pub mod render;
+pub mod renderer;
+pub mod error;
+pub mod gif_output;
+pub mod sequence;
+pub mod video_output;
+pub mod keyframe;
+pub mod animation;
\ No newline at end of file
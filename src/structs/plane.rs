@@ -1,4 +1,4 @@
-use super::{vec3::Vec3, shape::Shape, ray::Ray, appearance::Appearance};
+use super::{vec3::Vec3, shape::Shape, ray::Ray, appearance::Appearance, hit::Hit};
 
 pub struct Plane {
     pub point:Vec3,
@@ -7,22 +7,29 @@ pub struct Plane {
 }
 
 impl Shape for Plane {
-    fn intersections(&self, ray: &Ray) -> Vec<f64> {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
         let angle = ray.direction.dot(&self.normal);
 
         if angle.abs() < f64::EPSILON {
             vec![]
         } else {
-            vec![(self.point - ray.origin).dot(&self.normal) / angle]
+            let distance = (self.point - ray.origin).dot(&self.normal) / angle;
+            let hit = Hit::new(ray, distance, self.normal, &self.appearance);
+
+            // uv are just world units along two directions lying in the plane
+            let (tangent, bitangent) = self.tangents();
+            let offset = Vec3::between(&self.point, &hit.point);
+
+            vec![hit.with_uv(offset.dot(&tangent), offset.dot(&bitangent))]
         }
     }
 
-    fn appearance(&self) -> Appearance {
-        self.appearance.clone()
+    fn location_mut(&mut self) -> Option<&mut Vec3> {
+        Some(&mut self.point)
     }
 
-    fn normal_at(&self, _point: &Vec3, _ray: &Ray) -> Vec3 {
-        self.normal
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        Some(&mut self.appearance)
     }
 }
 
@@ -34,4 +41,11 @@ impl Plane {
             appearance
         }
     }
+
+    fn tangents(&self) -> (Vec3, Vec3) {
+        let helper = if self.normal.x.abs() < 0.9 { Vec3::I } else { Vec3::K };
+        let tangent = helper.cross(&self.normal).unit();
+
+        (tangent, self.normal.cross(&tangent))
+    }
 }
\ No newline at end of file
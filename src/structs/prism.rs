@@ -1,4 +1,4 @@
-use super::{vec3::Vec3, shape::Shape, util::{fmin, fmax}, ray::Ray, appearance::Appearance};
+use super::{vec3::Vec3, shape::Shape, util::{fmin, fmax}, ray::Ray, appearance::Appearance, bvh::Aabb, hit::Hit};
 
 pub struct Prism { 
     pub corner_ll: Vec3,
@@ -7,8 +7,8 @@ pub struct Prism {
 }
 
 impl Shape for Prism {
-    fn intersections(&self, ray: &Ray) -> Vec<f64> {
-        let mut res: Vec<f64> = Vec::new();
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
+        let mut res: Vec<Hit> = Vec::new();
 
         for axis in [Vec3::I, Vec3::J, Vec3::K] {
             res.append(self.intersect_on_axis(&axis, ray).as_mut());
@@ -17,20 +17,12 @@ impl Shape for Prism {
         res
     }
 
-    fn appearance(&self) -> Appearance {
-        self.appearance.clone()
+    fn bounds(&self) -> Option<Aabb> {
+        Some(Aabb::new(self.corner_ll, self.corner_ur))
     }
 
-    fn normal_at(&self, point: &Vec3, _ray: &Ray) -> Vec3 {
-        for axis in [Vec3::I, Vec3::J, Vec3::K] {
-            if (self.corner_ll.component(&axis) - point.component(&axis)).abs() < 0.00001 {
-                return axis.invert();
-            } else if (self.corner_ur.component(&axis) - point.component(&axis)).abs() < 0.00001 { 
-                return axis;
-            }
-        }
-
-        panic!("Given point is not on the surface of this prism: {:?}", point);
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        Some(&mut self.appearance)
     }
 }
 
@@ -43,17 +35,29 @@ impl Prism {
         }
     }
 
-    pub fn intersect_on_axis(&self, axis: &Vec3, ray: &Ray) -> Vec<f64> {
+    pub fn intersect_on_axis(&self, axis: &Vec3, ray: &Ray) -> Vec<Hit<'_>> {
         let other_axes: Vec<Vec3> = vec![Vec3::I, Vec3::J, Vec3::K].into_iter().filter(|v| v != axis).collect();
+        let axis_index = [Vec3::I, Vec3::J, Vec3::K].iter().position(|v| v == axis).unwrap_or(0);
 
         let mut intersections = vec![];
 
         if ray.direction.component(axis).abs() > f64::EPSILON {
-            for vertex in [self.corner_ll, self.corner_ur] {
+            // The face through corner_ll looks down the negative axis, the one through corner_ur down the positive one
+            for (side, (vertex, normal)) in [(self.corner_ll, axis.invert()), (self.corner_ur, *axis)].into_iter().enumerate() {
                 let intersect = (vertex.component(axis) - ray.origin.component(axis)) / ray.direction.component(axis);
                 let point = ray.origin + (ray.direction * intersect);
                 if self.contains(&point, &other_axes[0]) && self.contains(&point, &other_axes[1]) {
-                    intersections.push(intersect);
+                    // a face with no width along one of its sides gets a u or v of 0 there, rather than 0 / 0
+                    let [u, v] = [other_axes[0], other_axes[1]].map(|other| {
+                        let extent = self.corner_ur.component(&other) - self.corner_ll.component(&other);
+                        if extent > 0.0 { (point.component(&other) - self.corner_ll.component(&other)) / extent } else { 0.0 }
+                    });
+
+                    intersections.push(
+                        Hit::new(ray, intersect, normal, &self.appearance)
+                            .with_uv(u, v)
+                            .with_primitive(axis_index * 2 + side)
+                    );
                 }
             }
         }
@@ -64,4 +68,29 @@ impl Prism {
     pub fn contains(&self, point: &Vec3, axis: &Vec3) -> bool {
         self.corner_ll.component(axis) < point.component(axis) && point.component(axis) < self.corner_ur.component(axis)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{prism::Prism, shape::Shape, appearance::Appearance, finish::Finish, color::Color, ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn faces_are_numbered_and_mapped() {
+        let prism = Prism::new(Vec3::new(2, 4, 1), Vec3::O, Appearance::new(Color::WHITE, Finish::DEFAULT));
+        let ray = Ray::new(Vec3::new(1, 1, -5), Vec3::K);
+
+        // in through the face facing -z (2 * z's index), out through the one facing +z
+        let mut hits = prism.intersections(&ray);
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        assert_eq!(hits.iter().map(|hit| hit.primitive).collect::<Vec<_>>(), vec![4, 5]);
+        assert_eq!((hits[0].uv, hits[0].front_face, hits[1].front_face), ((0.5, 0.25), true, false));
+
+        // a prism with no thickness still gets proper coordinates wherever it's hit
+        let flat = Prism::new(Vec3::O, Vec3::new(2, 4, 0), Appearance::new(Color::WHITE, Finish::DEFAULT));
+        let directions = [Vec3::K, Vec3::new(1, 1, 1), Vec3::new(-1, 0, 2), Vec3::new(0, 1, 3)];
+        let hits: Vec<_> = directions.iter().flat_map(|direction| flat.intersections(&Ray::new(Vec3::new(1, 1, -5), *direction))).collect();
+
+        assert!(!hits.is_empty());
+        assert!(hits.iter().all(|hit| hit.uv.0.is_finite() && hit.uv.1.is_finite()));
+    }
+}
@@ -1,9 +1,7 @@
-use image::Rgba;
+use crate::structs::color::Color;
 use crate::structs::vec3::Vec3;
 use crate::structs::scene::Scene;
 
-use super::shape::Shape;
-
 #[derive(Debug)]
 pub struct Ray {
     pub origin: Vec3,
@@ -20,28 +18,14 @@ impl Ray {
         }
     }
 
-    pub fn trace(&self, scene: &Scene, depth: i32) -> Rgba<u8> {
+    pub fn trace(&self, scene: &Scene, depth: i32) -> Color {
         if depth > Self::MAX_DEPTH {
             return scene.background
         }
 
-        let objects_and_distances: Vec<(&Box<dyn Shape + Send + Sync>, f64)> = scene.shapes.iter().map(|s: &Box<dyn Shape + Send + Sync>| (s, s.closest_distance_along_ray(self))).collect();
-
-        if objects_and_distances.is_empty() {
-            println!("No objects in scene");
-            scene.background
-        } else {
-            let &(nearest_shape, shortest_distance) = objects_and_distances.iter().reduce(|acc, cur| if acc.1 < cur.1 {acc} else {cur}).unwrap();
-
-            if shortest_distance.is_infinite() {
-                scene.background
-            } else {
-                // No illumination
-                // nearest_shape.color()
-
-                let point: Vec3 = self.origin + (self.direction * shortest_distance);
-                nearest_shape.color_at(&point, self, scene, depth + 1)
-            }
+        match scene.nearest_hit(self) {
+            None => scene.background,
+            Some((shape, hit)) => shape.color_at(&hit, self, scene, depth + 1)
         }
     }
 
@@ -50,6 +34,19 @@ impl Ray {
         incident - *normal * (incident.dot(normal)) * 2
     }
 
+    /// Snell's law. `normal` faces back against the ray and `eta` is the ratio of refractive indices
+    /// (from over to); None when the ray is totally internally reflected instead.
+    pub fn refract(&self, normal: &Vec3, eta: f64) -> Option<Vec3> {
+        let cos_i = -self.direction.dot(normal);
+        let sin2_t = eta * eta * (1.0 - cos_i * cos_i);
+
+        if sin2_t > 1.0 {
+            None
+        } else {
+            Some(self.direction * eta + *normal * (eta * cos_i - (1.0 - sin2_t).sqrt()))
+        }
+    }
+
     // bool intersect_triangle(
     //     in Ray R, in vec3 A, in vec3 B, in vec3 C, out float t, 
     //     out float u, out float v, out vec3 N
@@ -88,4 +85,24 @@ impl Ray {
             None
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{ray::Ray, vec3::Vec3};
+
+    #[test]
+    fn refraction() {
+        // 45 degrees into glass, against a normal facing back up the ray
+        let ray = Ray::new(Vec3::O, Vec3::new(1, 1, 0));
+        let normal = Vec3::new(0, -1, 0);
+
+        let refracted = ray.refract(&normal, 1.0 / 1.5).unwrap();
+        let sin_out = refracted.cross(&normal).length();
+        assert!((sin_out - 45f64.to_radians().sin() / 1.5).abs() < 1e-9);
+        assert!((refracted.length() - 1.0).abs() < 1e-9);
+
+        // and the same angle coming back out is past the critical angle
+        assert!(ray.refract(&normal, 1.5).is_none());
+    }
+}
@@ -0,0 +1,146 @@
+use std::{sync::atomic::{AtomicUsize, Ordering}, thread};
+
+use super::{color::Color, framebuffer::Framebuffer, sampler::Sampler, scene::Scene};
+
+/// A rectangle of the image, rendered by one thread into its own buffers.
+struct Tile {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    samples: Vec<usize>
+}
+
+/// Splits frames into square tiles and hands them out to a pool of threads. Threads take the
+/// next tile off a shared counter as soon as they finish one, so an expensive corner of the
+/// picture doesn't leave the rest of the cores waiting. Nothing is locked while tracing; the
+/// tiles get copied into the framebuffer once every thread is done.
+#[derive(Debug, Copy, Clone)]
+pub struct TileRenderer {
+    // at least one thread runs, even if this is 0
+    pub threads: usize,
+    // side of each tile in pixels; smaller tiles balance better, larger ones cost less to hand out
+    pub tile_size: usize
+}
+
+impl TileRenderer {
+    pub const DEFAULT_TILE_SIZE: usize = 32;
+
+    /// Uses `threads` threads, or one per core when that's 0.
+    pub fn new(threads: usize) -> Self {
+        let threads = match threads {
+            0 => thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n
+        };
+
+        Self {
+            threads,
+            tile_size: Self::DEFAULT_TILE_SIZE
+        }
+    }
+
+    /// Renders one frame, calling `progress` with the number of tiles finished and the total
+    /// after each one. That happens on the render threads, in whatever order they finish.
+    pub fn render<P>(&self, scene: &Scene, sampler: &Sampler, width: usize, height: usize, progress: P) -> Framebuffer
+        where P: Fn(usize, usize) + Sync
+    {
+        let size = self.tile_size.max(1);
+        let (across, down) = (width.div_ceil(size), height.div_ceil(size));
+        let (next, finished) = (AtomicUsize::new(0), AtomicUsize::new(0));
+
+        let tiles: Vec<Tile> = thread::scope(|scope| {
+            let workers: Vec<_> = (0..self.threads.max(1)).map(|_| scope.spawn(|| {
+                let mut done = Vec::new();
+
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= across * down {
+                        break done;
+                    }
+
+                    let (x, y) = ((index % across) * size, (index / across) * size);
+                    done.push(Self::render_tile(scene, sampler, (x, y), (size.min(width - x), size.min(height - y)), (width, height)));
+                    progress(finished.fetch_add(1, Ordering::Relaxed) + 1, across * down);
+                }
+            })).collect();
+
+            workers.into_iter().flat_map(|worker| worker.join().expect("Render thread panicked")).collect()
+        });
+
+        let mut framebuffer = Framebuffer::new(width, height);
+
+        for tile in tiles {
+            for (i, (color, samples)) in tile.pixels.into_iter().zip(tile.samples).enumerate() {
+                framebuffer.set(tile.x + i % tile.width, tile.y + i / tile.width, color, samples);
+            }
+        }
+
+        framebuffer
+    }
+
+    fn render_tile(scene: &Scene, sampler: &Sampler, (x, y): (usize, usize), (width, height): (usize, usize), image: (usize, usize)) -> Tile {
+        let mut tile = Tile {
+            x,
+            y,
+            width,
+            height,
+            pixels: Vec::with_capacity(width * height),
+            samples: Vec::with_capacity(width * height)
+        };
+
+        // adaptive sampling starts each pixel from its corners, which it shares with its neighbours,
+        // so they get traced a row at a time and the bottom row kept as the next one's top
+        let corner_row = |py: usize| -> Vec<Color> { (x..=x + width).map(|px| sampler.corner(scene, px, py, image.0, image.1)).collect() };
+        let mut top = sampler.adaptive.map(|_| corner_row(y));
+
+        for py in y..y + tile.height {
+            let bottom = top.as_ref().map(|_| corner_row(py + 1));
+
+            for (i, px) in (x..x + tile.width).enumerate() {
+                let (color, samples) = match (&top, &bottom) {
+                    (Some(top), Some(bottom)) => sampler.pixel_from_corners(scene, px, py, image.0, image.1, [top[i], top[i + 1], bottom[i], bottom[i + 1]]),
+                    _ => sampler.pixel(scene, px, py, image.0, image.1)
+                };
+
+                tile.pixels.push(color);
+                tile.samples.push(samples);
+            }
+
+            top = bottom;
+        }
+
+        tile
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{render::TileRenderer, sampler::Sampler, scene::Scene, camera::{Camera, FieldOfView}, sphere::Sphere, appearance::Appearance, finish::Finish, color::Color, vec3::Vec3};
+
+    #[test]
+    fn tiles_cover_the_frame() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        scene.add_shape(Box::new(Sphere::new(Vec3::new(0, 0, 5), 2.0, Appearance::new(Color::BLACK, Finish::DEFAULT))));
+
+        let sampler = Sampler::DEFAULT;
+
+        // sizes that don't divide evenly into tiles, rendered on one thread and on several
+        let single = TileRenderer { threads: 1, tile_size: 7 }.render(&scene, &sampler, 30, 17, |_, _| ());
+        let many = TileRenderer { threads: 5, tile_size: 7 }.render(&scene, &sampler, 30, 17, |_, _| ());
+
+        assert!(single.samples.iter().all(|&count| count == 1));
+        assert_eq!(single.pixels, many.pixels);
+
+        // no threads still means one, rather than a blank frame
+        assert_eq!(TileRenderer { threads: 0, tile_size: 7 }.render(&scene, &sampler, 30, 17, |_, _| ()).pixels, single.pixels);
+
+        // corners shared between pixels come out the same as tracing each pixel on its own
+        let adaptive = sampler.with_adaptive(0.1, 64);
+        let tiled = TileRenderer { threads: 3, tile_size: 7 }.render(&scene, &adaptive, 30, 17, |_, _| ());
+        let (pixels, samples): (Vec<Color>, Vec<usize>) = (0..30 * 17).map(|i| adaptive.pixel(&scene, i % 30, i / 30, 30, 17)).unzip();
+
+        assert_eq!(tiled.pixels, pixels);
+        assert_eq!(tiled.samples, samples);
+    }
+}
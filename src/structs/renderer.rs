@@ -0,0 +1,194 @@
+use std::ops::Range;
+
+use super::{animate::Animate, framebuffer::Framebuffer, render::TileRenderer, sampler::Sampler, scene::Scene};
+
+/// How far a render has got, passed to the progress callback after every tile.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Progress {
+    // counting from 0 within the frames being rendered, whatever their frame numbers; always 0 of 1 for a single frame
+    pub frame: usize,
+    pub frames: usize,
+    pub tiles_done: usize,
+    pub tiles: usize
+}
+
+impl Progress {
+    /// From 0 to 1 across all of the frames.
+    pub fn fraction(&self) -> f64 {
+        (self.frame as f64 + self.tiles_done as f64 / self.tiles.max(1) as f64) / self.frames.max(1) as f64
+    }
+}
+
+type ProgressCallback = Box<dyn Fn(&Progress) + Send + Sync>;
+
+/// Renders scenes into framebuffers of linear colour, one frame at a time or over a stretch of
+/// the animation. What happens to the frames afterwards (tone mapping, saving, encoding) is up to
+/// the caller.
+pub struct Renderer {
+    pub width: usize,
+    pub height: usize,
+    pub sampler: Sampler,
+    pub tiles: TileRenderer,
+    progress: Option<ProgressCallback>
+}
+
+impl Renderer {
+    /// A `width` by `height` renderer taking one sample per pixel, on every core.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            sampler: Sampler::DEFAULT,
+            tiles: TileRenderer::new(0),
+            progress: None
+        }
+    }
+
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = sampler;
+        self
+    }
+
+    /// Renders on `threads` threads, or one per core when that's 0.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.tiles = TileRenderer { tile_size: self.tiles.tile_size, ..TileRenderer::new(threads) };
+        self
+    }
+
+    /// Calls `callback` after every finished tile. It runs on the render threads, so it should be quick.
+    pub fn on_progress<F>(mut self, callback: F) -> Self
+        where F: Fn(&Progress) + Send + Sync + 'static
+    {
+        self.progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Renders the scene as it stands. The camera's film gets fitted to the output's aspect ratio first,
+    /// and the scene's BVH rebuilt if its shapes have changed.
+    pub fn render_frame(&self, scene: &mut Scene) -> Framebuffer {
+        self.render(scene, 0, 1)
+    }
+
+    /// How many frames there are in `duration` seconds: frame `n` shows the scene `n / frame_rate` seconds in.
+    /// A frame rate of 0 has no frames at all.
+    pub fn frame_count(duration: f64, frame_rate: usize) -> usize {
+        if frame_rate == 0 {
+            return 0;
+        }
+
+        (duration.max(0.0) * frame_rate as f64).ceil() as usize
+    }
+
+    /// Renders the scene as it is `time` seconds into the animation.
+    pub fn render_at(&self, scene: &mut Scene, time: f64) -> Framebuffer {
+        scene.set_time(time);
+        self.render(scene, 0, 1)
+    }
+
+    /// Renders the given frames of the animation, handing each to `frame` along with its frame
+    /// number as soon as it's done. Stops at the first error `frame` returns. Each frame only
+    /// depends on its own time, so a render can pick up where an earlier one left off, or be split
+    /// into ranges and shared out between processes. Within one, frames go one at a time, as the
+    /// scene is moved to each frame's time in place; every frame still uses all of the threads.
+    /// Nothing gets rendered at a frame rate of 0, as no frame has a time.
+    pub fn render_frames<F, E>(&self, scene: &mut Scene, frames: Range<usize>, frame_rate: usize, mut frame: F) -> Result<(), E>
+        where F: FnMut(usize, Framebuffer) -> Result<(), E>
+    {
+        if frame_rate == 0 {
+            return Ok(());
+        }
+
+        let count = frames.len();
+
+        for (i, n) in frames.enumerate() {
+            scene.set_time(n as f64 / frame_rate as f64);
+            frame(n, self.render(scene, i, count))?;
+        }
+
+        Ok(())
+    }
+
+    fn render(&self, scene: &mut Scene, frame: usize, frames: usize) -> Framebuffer {
+        scene.camera.set_aspect(self.width as f64 / self.height as f64);
+
+        if !scene.bvh_is_current() {
+            scene.build_bvh();
+        }
+
+        self.tiles.render(scene, &self.sampler, self.width, self.height, |tiles_done, tiles| {
+            if let Some(progress) = &self.progress {
+                progress(&Progress { frame, frames, tiles_done, tiles });
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    use crate::structs::{renderer::Renderer, scene::Scene, camera::{Camera, FieldOfView}, color::Color, vec3::Vec3, sphere::Sphere, appearance::Appearance, finish::Finish};
+
+    #[test]
+    fn renders_frames() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        scene.camera.add_camera_move(Vec3::new(0, 0, -10), 10.0, None);
+
+        let tiles = Arc::new(AtomicUsize::new(0));
+        let counter = tiles.clone();
+        let renderer = Renderer::new(40, 20).with_threads(2).on_progress(move |progress| {
+            assert!(progress.fraction() > 0.0 && progress.fraction() <= 1.0);
+            counter.fetch_add(1, Ordering::Relaxed);
+        });
+
+        let mut frames = Vec::new();
+        renderer.render_frames(&mut scene, 4..8, 4, |n, framebuffer| {
+            assert_eq!((framebuffer.width, framebuffer.height), (40, 20));
+            frames.push(n);
+            Ok::<(), ()>(())
+        }).unwrap();
+
+        // frames keep their numbers, and the last is 7/4 of the way along a one-unit-a-second move
+        assert_eq!(frames, vec![4, 5, 6, 7]);
+        assert_eq!(scene.camera.location, Vec3::new(0.0, 0.0, -1.75));
+        assert_eq!(tiles.load(Ordering::Relaxed), 4 * 2);
+
+        // and any one of them can be gone back to
+        renderer.render_at(&mut scene, 0.5);
+        assert_eq!(scene.camera.location, Vec3::new(0.0, 0.0, -0.5));
+        assert_eq!(Renderer::frame_count(2.1, 4), 9);
+
+        // without a frame rate there's nothing to render
+        assert_eq!(Renderer::frame_count(2.1, 0), 0);
+        renderer.render_frames(&mut scene, 0..2, 0, |_, _| Err(())).unwrap();
+    }
+
+    #[test]
+    fn frames_the_output_size() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Vertical(60.0)), Color::WHITE);
+
+        // the camera takes on the output's shape, keeping the height it was given
+        for (width, height) in [(40, 20), (10, 30)] {
+            let framebuffer = Renderer::new(width, height).render_frame(&mut scene);
+            assert_eq!((framebuffer.width, framebuffer.height, framebuffer.pixels.len()), (width, height, width * height));
+
+            let aspect = width as f64 / height as f64;
+            assert!((scene.camera.camera_right.length() / scene.camera.camera_up.length() - aspect).abs() < 1e-9);
+            assert!((scene.camera.camera_up.length() - 30f64.to_radians().tan()).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn rebuilds_a_stale_bvh() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        scene.add_shape(Box::new(Sphere::new(Vec3::new(0, 0, -5), 1.0, Appearance::new(Color::BLACK, Finish::DEFAULT))));
+        scene.build_bvh();
+
+        *scene.shapes_mut()[0].location_mut().unwrap() = Vec3::new(0, 0, 5);
+        assert!(!scene.bvh_is_current());
+
+        let framebuffer = Renderer::new(20, 20).render_frame(&mut scene);
+        assert!(scene.bvh_is_current());
+        assert_ne!(framebuffer.pixels[20 * 10 + 10], Color::WHITE);
+    }
+}
@@ -0,0 +1,360 @@
+use std::str::FromStr;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::{color::Color, scene::Scene};
+
+/// Where the samples for one pixel land.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SamplePattern {
+    // the centre of each cell of an even grid; the same for every pixel
+    Stratified,
+    // somewhere random inside each cell of the grid, trading regular aliasing for noise
+    Jittered
+}
+
+impl FromStr for SamplePattern {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "stratified" | "grid" => Ok(SamplePattern::Stratified),
+            "jittered" | "jitter" => Ok(SamplePattern::Jittered),
+            other => Err(format!("Unknown sampling pattern `{}` (expected stratified or jittered)", other))
+        }
+    }
+}
+
+/// How much each sample counts towards a pixel, by its offset from the pixel's centre.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3; slightly sharpening, so weights can go negative
+    Mitchell
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "box" => Ok(Filter::Box),
+            "tent" | "triangle" => Ok(Filter::Tent),
+            "gaussian" => Ok(Filter::Gaussian),
+            "mitchell" => Ok(Filter::Mitchell),
+            other => Err(format!("Unknown filter `{}` (expected box, tent, gaussian or mitchell)", other))
+        }
+    }
+}
+
+impl Filter {
+    const GAUSSIAN_FALLOFF: f64 = 2.0;
+
+    /// How far from the pixel's centre, in pixels, samples still count.
+    pub fn radius(&self) -> f64 {
+        match self {
+            Filter::Box => 0.5,
+            Filter::Tent => 1.0,
+            Filter::Gaussian => 1.5,
+            Filter::Mitchell => 2.0
+        }
+    }
+
+    pub fn weight(&self, dx: f64, dy: f64) -> f64 {
+        self.weight_1d(dx) * self.weight_1d(dy)
+    }
+
+    // every filter here is separable, so this is the weight along one axis
+    fn weight_1d(&self, d: f64) -> f64 {
+        let (d, radius) = (d.abs(), self.radius());
+
+        if d > radius {
+            return 0.0;
+        }
+
+        match self {
+            Filter::Box => 1.0,
+            Filter::Tent => radius - d,
+            // shifted down so that it reaches zero at the edge instead of stopping abruptly
+            Filter::Gaussian => (-Self::GAUSSIAN_FALLOFF * d * d).exp() - (-Self::GAUSSIAN_FALLOFF * radius * radius).exp(),
+            Filter::Mitchell => {
+                let (b, c) = (1.0 / 3.0, 1.0 / 3.0);
+
+                if d < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * d.powi(3) + (-18.0 + 12.0 * b + 6.0 * c) * d.powi(2) + (6.0 - 2.0 * b)) / 6.0
+                } else {
+                    ((-b - 6.0 * c) * d.powi(3) + (6.0 * b + 30.0 * c) * d.powi(2) + (-12.0 * b - 48.0 * c) * d + (8.0 * b + 24.0 * c)) / 6.0
+                }
+            }
+        }
+    }
+}
+
+/// Settings for sampling only where the picture changes: each pixel starts from its four corners,
+/// and any square whose corners differ by more than `threshold` gets split into four, recursively.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Adaptive {
+    // largest difference in any channel, with colours clipped to the displayable range first
+    pub threshold: f64,
+    // no pixel takes more samples than this, counting its corners
+    pub max_samples: usize
+}
+
+/// Turns several camera rays into one pixel. Each pixel gets its own random numbers, seeded from
+/// `seed` and its position, so a render comes out the same however the work is split between threads.
+#[derive(Debug, Copy, Clone)]
+pub struct Sampler {
+    // laid out in rows as near square as the count allows, the last row taking whatever is left over
+    pub samples: usize,
+    pub pattern: SamplePattern,
+    pub filter: Filter,
+    pub seed: u64,
+    // when set, replaces the even spread of `samples` with subdivision by contrast
+    pub adaptive: Option<Adaptive>
+}
+
+impl Sampler {
+    // one sample through the middle of each pixel
+    pub const DEFAULT: Sampler = Sampler {
+        samples: 1,
+        pattern: SamplePattern::Stratified,
+        filter: Filter::Box,
+        seed: 0,
+        adaptive: None
+    };
+
+    pub fn new(samples: usize, pattern: SamplePattern, filter: Filter, seed: u64) -> Self {
+        Self {
+            samples,
+            pattern,
+            filter,
+            seed,
+            adaptive: None
+        }
+    }
+
+    pub fn with_adaptive(mut self, threshold: f64, max_samples: usize) -> Self {
+        self.adaptive = Some(Adaptive { threshold, max_samples: max_samples.max(4) });
+        self
+    }
+
+    /// The colour of pixel (x, y) in a `width` by `height` image, and how many samples went into it.
+    pub fn pixel(&self, scene: &Scene, x: usize, y: usize, width: usize, height: usize) -> (Color, usize) {
+        if self.adaptive.is_some() {
+            let corner = |cx, cy| self.corner(scene, cx, cy, width, height);
+            return self.pixel_from_corners(scene, x, y, width, height, [corner(x, y), corner(x + 1, y), corner(x, y + 1), corner(x + 1, y + 1)]);
+        }
+
+        let mut rng = self.pixel_rng(x, y, width);
+
+        // an open lens needs enough rays to cover it
+        let mut samples = self.samples.max(1);
+        if scene.camera.aperture > 0.0 {
+            samples = samples.max(scene.camera.lens_samples);
+        }
+
+        let side = (samples as f64).sqrt().ceil() as usize;
+        let rows = samples.div_ceil(side);
+        let radius = self.filter.radius();
+
+        let mut color = Color::BLACK;
+        let mut total_weight = 0.0;
+
+        for i in 0..samples {
+            let (u, v) = match self.pattern {
+                SamplePattern::Stratified => (0.5, 0.5),
+                SamplePattern::Jittered => (rng.gen(), rng.gen())
+            };
+
+            // a short last row spreads its cells out wider, so the rows still cover the whole pixel
+            let (row, col) = (i / side, i % side);
+            let columns = if row + 1 == rows { samples - side * row } else { side };
+
+            // offset from the pixel's centre, spread over the filter's whole footprint
+            let dx = (col as f64 + u) * 2.0 * radius / columns as f64 - radius;
+            let dy = (row as f64 + v) * 2.0 * radius / rows as f64 - radius;
+
+            let weight = self.filter.weight(dx, dy);
+            if weight == 0.0 {
+                continue;
+            }
+
+            let film_x = (x as f64 + 0.5 + dx) / width as f64 - 0.5;
+            let film_y = (y as f64 + 0.5 + dy) / height as f64 - 0.5;
+
+            color += scene.trace(film_x, film_y, &mut rng) * weight;
+            total_weight += weight;
+        }
+
+        if total_weight.abs() < 1e-12 {
+            return (Color::BLACK, samples);
+        }
+
+        (color * (1.0 / total_weight), samples)
+    }
+
+    /// With adaptive sampling, the same as `pixel`, but starting from the pixel's top left, top right, bottom left
+    /// and bottom right corners as already traced by `corner`, so that neighbouring pixels needn't trace them again.
+    /// Without it, the corners go unused.
+    pub fn pixel_from_corners(&self, scene: &Scene, x: usize, y: usize, width: usize, height: usize, corners: [Color; 4]) -> (Color, usize) {
+        let Some(adaptive) = self.adaptive else {
+            return self.pixel(scene, x, y, width, height);
+        };
+
+        let mut rng = self.pixel_rng(x, y, width);
+
+        // positions here are in pixels, from the image's top left corner
+        let mut trace = |px: f64, py: f64| scene.trace(px / width as f64 - 0.5, py / height as f64 - 0.5, &mut rng);
+
+        // the splits the lens forces are counted up front, so that contrast can't spend their share first
+        let (min_size, mut count) = adaptive.min_size(scene);
+        let color = adaptive.refine(&mut trace, (x as f64, y as f64, 1.0), corners, min_size, &mut count);
+
+        (color, count)
+    }
+
+    /// The colour at (x, y) on the grid of pixel corners, which runs one past the last pixel each way.
+    /// Each corner gets its own random numbers, so it comes out the same whichever pixel traces it.
+    pub fn corner(&self, scene: &Scene, x: usize, y: usize, width: usize, height: usize) -> Color {
+        let mut rng = StdRng::seed_from_u64(self.seed.wrapping_mul(0xC2B2_AE3D_27D4_EB4F).wrapping_add((y * (width + 1) + x) as u64));
+        scene.trace(x as f64 / width as f64 - 0.5, y as f64 / height as f64 - 0.5, &mut rng)
+    }
+
+    fn pixel_rng(&self, x: usize, y: usize, width: usize) -> StdRng {
+        StdRng::seed_from_u64(self.seed.wrapping_mul(0x9E37_79B9_7F4A_7C15).wrapping_add((y * width + x) as u64))
+    }
+}
+
+impl Adaptive {
+    // An open lens needs enough rays to cover it whatever the contrast says, so every square gets split, a level
+    // at a time, until there are at least the camera's `lens_samples`, or as many as `max_samples` allows.
+    // Returns the size squares get split down to, and how many samples that takes, corners included.
+    fn min_size(&self, scene: &Scene) -> (f64, usize) {
+        let (mut min_size, mut samples) = (1.0, 4);
+
+        if scene.camera.aperture > 0.0 {
+            let mut squares = 1;
+
+            while samples < scene.camera.lens_samples && samples + 5 * squares <= self.max_samples {
+                samples += 5 * squares;
+                squares *= 4;
+                min_size /= 2.0;
+            }
+        }
+
+        (min_size, samples)
+    }
+
+    // Averages the square at (x, y) with sides `size`, given the colours at its top left, top right,
+    // bottom left and bottom right corners. Splitting it takes five more samples: the middle of
+    // each side and the centre. Squares bigger than `min_size` always get split, and `count`
+    // should already include the samples that takes.
+    fn refine<F>(&self, trace: &mut F, (x, y, size): (f64, f64, f64), corners: [Color; 4], min_size: f64, count: &mut usize) -> Color
+        where F: FnMut(f64, f64) -> Color
+    {
+        let [top_left, top_right, bottom_left, bottom_right] = corners;
+
+        if size <= min_size {
+            if contrast(&corners) <= self.threshold || *count + 5 > self.max_samples {
+                return (top_left + top_right + bottom_left + bottom_right) * 0.25;
+            }
+
+            *count += 5;
+        }
+
+        let half = size / 2.0;
+
+        let top = trace(x + half, y);
+        let left = trace(x, y + half);
+        let centre = trace(x + half, y + half);
+        let right = trace(x + size, y + half);
+        let bottom = trace(x + half, y + size);
+
+        let quarters = [
+            self.refine(trace, (x, y, half), [top_left, top, left, centre], min_size, count),
+            self.refine(trace, (x + half, y, half), [top, top_right, centre, right], min_size, count),
+            self.refine(trace, (x, y + half, half), [left, centre, bottom_left, bottom], min_size, count),
+            self.refine(trace, (x + half, y + half, half), [centre, right, bottom, bottom_right], min_size, count)
+        ];
+
+        quarters.into_iter().fold(Color::BLACK, |acc, c| acc + c) * 0.25
+    }
+}
+
+// The biggest difference in any channel between any two of the colours. Anything brighter than
+// white counts as white, so that highlights don't keep splitting squares that already look the same.
+fn contrast(colors: &[Color]) -> f64 {
+    let clipped: Vec<Color> = colors.iter().map(|color| color.map(|c| c.clamp(0.0, 1.0))).collect();
+    let mut most: f64 = 0.0;
+
+    for (i, a) in clipped.iter().enumerate() {
+        for b in &clipped[i + 1..] {
+            most = most.max((a.r - b.r).abs()).max((a.g - b.g).abs()).max((a.b - b.b).abs());
+        }
+    }
+
+    most
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{sampler::{Filter, SamplePattern, Sampler}, scene::Scene, camera::{Camera, FieldOfView}, sphere::Sphere, appearance::Appearance, finish::Finish, color::Color, vec3::Vec3};
+
+    #[test]
+    fn filters() {
+        for filter in [Filter::Box, Filter::Tent, Filter::Gaussian, Filter::Mitchell] {
+            // heaviest in the middle, and nothing past the edge
+            assert!(filter.weight(0.0, 0.0) > 0.0);
+            assert!(filter.weight(0.0, 0.0) >= filter.weight(0.3, 0.2), "{:?}", filter);
+            assert_eq!(filter.weight(filter.radius() + 0.01, 0.0), 0.0);
+        }
+
+        // Mitchell's outer lobe dips below zero
+        assert!(Filter::Mitchell.weight(1.5, 0.0) < 0.0);
+
+        assert_eq!("Gaussian".parse::<Filter>(), Ok(Filter::Gaussian));
+        assert!("lanczos".parse::<Filter>().is_err());
+    }
+    #[test]
+    fn takes_the_samples_asked_for() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        scene.add_shape(Box::new(Sphere::new(Vec3::new(0, 0, 5), 1.0, Appearance::new(Color::BLACK, Finish::DEFAULT))));
+
+        // not rounded up to fill a square
+        for samples in [1, 2, 3, 5, 9] {
+            assert_eq!(Sampler::new(samples, SamplePattern::Jittered, Filter::Box, 0).pixel(&scene, 7, 10, 20, 20).1, samples);
+        }
+
+        // and by default, one through the middle of the pixel, so edges don't shimmer
+        for x in 0..20 {
+            let centre = scene.trace((x as f64 + 0.5) / 20.0 - 0.5, 10.5 / 20.0 - 0.5, &mut rand::thread_rng());
+            assert_eq!(Sampler::DEFAULT.pixel(&scene, x, 10, 20, 20), (centre, 1));
+        }
+    }
+
+    #[test]
+    fn adaptive_refines_edges() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        scene.add_shape(Box::new(Sphere::new(Vec3::new(0, 0, 5), 1.0, Appearance::new(Color::BLACK, Finish::DEFAULT))));
+
+        let sampler = Sampler::new(1, SamplePattern::Jittered, Filter::Box, 0).with_adaptive(0.1, 64);
+        let row: Vec<usize> = (0..20).map(|x| sampler.pixel(&scene, x, 10, 20, 20).1).collect();
+
+        // flat background stops at the corners, while the sphere's outline gets split up to the limit
+        assert_eq!(row[0], 4);
+        assert!(row.iter().any(|&count| count > 20), "{:?}", row);
+        assert!(row.iter().all(|&count| count <= 64));
+
+        // with the lens open, even the flat background takes enough samples to cover it
+        (scene.camera.aperture, scene.camera.lens_samples) = (0.1, 16);
+        assert!(sampler.pixel(&scene, 0, 10, 20, 20).1 >= 16);
+
+        // as long as that fits under the limit
+        scene.camera.lens_samples = 100;
+        let row: Vec<usize> = (0..20).map(|x| sampler.pixel(&scene, x, 10, 20, 20).1).collect();
+        assert!(row.iter().all(|&count| count <= 64), "{:?}", row);
+        assert_eq!(sampler.with_adaptive(0.1, 10).pixel(&scene, 0, 10, 20, 20).1, 9);
+    }
+}
@@ -1,31 +1,220 @@
-use image::Rgba;
-use super::{camera::Camera, shape::Shape, light::Light, animate::Animate};
+use rand::Rng;
+
+use super::{color::Color, camera::Camera, shape::Shape, light::Light, animate::Animate, animation::Animation, bvh::{Aabb, Bvh}, ray::Ray, vec3::Vec3, hit::Hit};
 
 pub struct Scene<'a> {
     pub camera: Camera,
-    pub background: Rgba<u8>,
-    pub shapes: Vec<Box<dyn Shape + Send + Sync + 'a>>,
-    pub lights: Vec<Light>
+    pub background: Color,
+    shapes: Vec<Box<dyn Shape + Send + Sync + 'a>>,
+    pub lights: Vec<Light>,
+    // keyframed changes to everything but the camera, applied by `set_time`
+    pub animations: Vec<Animation>,
+    // acceleration structure over `shapes`, (re)built by `build_bvh`
+    bvh: Bvh,
+    bounded: Vec<usize>,
+    unbounded: Vec<usize>,
+    // set by anything that could add, move or replace a shape, until the BVH is rebuilt
+    bvh_stale: bool
 }
 
-impl Scene<'_> {
-    pub fn new(camera: Camera, background: Rgba<u8>) -> Self {
-        Scene { camera, background, shapes: Vec::new(), lights: Vec::new() }
+impl<'a> Scene<'a> {
+    pub fn new(camera: Camera, background: Color) -> Self {
+        Scene {
+            camera,
+            background,
+            shapes: Vec::new(),
+            lights: Vec::new(),
+            animations: Vec::new(),
+            bvh: Bvh::build(&[]),
+            bounded: Vec::new(),
+            unbounded: Vec::new(),
+            bvh_stale: false
+        }
+    }
+
+    pub fn shapes(&self) -> &[Box<dyn Shape + Send + Sync + 'a>] {
+        &self.shapes
+    }
+
+    /// The shapes, to add to or change. Whatever happens to them, the BVH counts as out of date
+    /// afterwards, and lookups test every shape until it's rebuilt.
+    pub fn shapes_mut(&mut self) -> &mut Vec<Box<dyn Shape + Send + Sync + 'a>> {
+        self.bvh_stale = true;
+        &mut self.shapes
+    }
+
+    pub fn add_shape(&mut self, shape: Box<dyn Shape + Send + Sync + 'a>) {
+        self.shapes_mut().push(shape);
+    }
+
+    /// Builds the top-level BVH over every shape's bounding box. `Renderer` does this itself, and
+    /// only when the shapes have changed since; anything else tracing the scene can call it whenever.
+    pub fn build_bvh(&mut self) {
+        let mut bounds: Vec<Aabb> = Vec::new();
+        self.bounded.clear();
+        self.unbounded.clear();
+
+        for (i, shape) in self.shapes.iter().enumerate() {
+            match shape.bounds() {
+                Some(b) => { bounds.push(b); self.bounded.push(i); },
+                None => self.unbounded.push(i)
+            }
+        }
+
+        self.bvh = Bvh::build(&bounds);
+        self.bvh_stale = false;
+    }
+
+    pub fn bvh_is_current(&self) -> bool {
+        !self.bvh_stale
     }
 
-    pub fn trace<T>(&self, x: T, y: T) -> Rgba<u8> 
-        where T: Into<f64> + Copy 
+    pub fn trace<T, R>(&self, x: T, y: T, rng: &mut R) -> Color
+        where T: Into<f64> + Copy, R: Rng
     {
-        self.camera.trace(self, x, y)
+        self.camera.trace(self, x, y, rng)
+    }
+
+    /// The closest surface along the ray, and the shape it belongs to.
+    pub fn nearest_hit(&self, ray: &Ray) -> Option<(&(dyn Shape + Send + Sync + 'a), Hit<'_>)> {
+        let hit_on = |i: usize| self.shapes[i].closest_hit(ray).map(|hit| (i, hit));
+        fn closer<'h>(acc: (usize, Hit<'h>), cur: (usize, Hit<'h>)) -> (usize, Hit<'h>) {
+            if acc.1.distance < cur.1.distance {acc} else {cur}
+        }
+
+        let nearest = if self.bvh_is_current() {
+            let mut bounded: Option<(usize, Hit)> = None;
+
+            self.bvh.nearest(ray, |i| {
+                let (index, hit) = hit_on(self.bounded[i])?;
+
+                if bounded.is_none_or(|(_, best)| hit.distance < best.distance) {
+                    bounded = Some((index, hit));
+                }
+
+                Some(hit.distance)
+            });
+
+            self.unbounded.iter().filter_map(|&i| hit_on(i)).chain(bounded).reduce(closer)
+        } else {
+            (0..self.shapes.len()).filter_map(hit_on).reduce(closer)
+        };
+
+        nearest.map(|(i, hit)| (self.shapes[i].as_ref(), hit))
+    }
+
+    /// How much of the light from `light_position` reaches `point`: white with nothing in the way, black
+    /// behind anything opaque, and tinted by whatever transparent surfaces it passes through.
+    pub fn shadow(&self, point: &Vec3, light_position: &Vec3) -> Color {
+        let mut light = Color::WHITE;
+        // takes in one shape's share, and says whether there's nothing left to block
+        let mut through = |shape: &(dyn Shape + Send + Sync + 'a)| {
+            light = light * shape.shadow(point, light_position);
+            light == Color::BLACK
+        };
+
+        let blocked = if self.bvh_is_current() {
+            let light_vector = Vec3::between(point, light_position);
+            let distance_to_light = light_vector.length();
+            let ray = Ray::new(*point, light_vector);
+
+            self.unbounded.iter().any(|&i| through(self.shapes[i].as_ref())) ||
+                self.bvh.any(&ray, distance_to_light, |i| through(self.shapes[self.bounded[i]].as_ref()))
+        } else {
+            self.shapes.iter().any(|shape| through(shape.as_ref()))
+        };
+
+        if blocked { Color::BLACK } else { light }
     }
 }
 
 impl Animate for Scene<'_> {
-    fn start(&mut self) {
-        todo!()
+    fn set_time(&mut self, time: f64) {
+        self.camera.set_time(time);
+
+        let animations = std::mem::take(&mut self.animations);
+
+        // anything that moves shapes leaves the BVH stale, for the renderer to rebuild
+        for animation in &animations {
+            animation.apply(self, time);
+        }
+
+        self.animations = animations;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{scene::Scene, camera::{Camera, FieldOfView}, sphere::Sphere, plane::Plane, appearance::Appearance, finish::Finish, color::Color, vec3::Vec3, ray::Ray};
+
+    // a row of spheres behind a floor, shuffled about so the BVH has some work to do
+    fn scene() -> Scene<'static> {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        let appearance = Appearance::new(Color::WHITE, Finish::DEFAULT);
+
+        for i in 0..40 {
+            let center = Vec3::new((i * 7 % 13) as f64 - 6.0, (i * 5 % 9) as f64 - 4.0, 5.0 + (i * 3 % 11) as f64);
+            scene.add_shape(Box::new(Sphere::new(center, 0.3 + (i % 4) as f64 * 0.2, appearance.clone())));
+        }
+
+        scene.add_shape(Box::new(Plane::new(Vec3::new(0, 5, 0), Vec3::new(0, -1, 0), appearance)));
+        scene
+    }
+
+    // a fan of rays from the origin, and the points along them to cast shadows from
+    fn rays() -> Vec<Ray> {
+        (0..400).map(|i| Ray::new(Vec3::O, Vec3::new((i % 20) as f64 / 10.0 - 1.0, (i / 20) as f64 / 10.0 - 1.0, 1.0))).collect()
     }
 
-    fn update(&mut self, delta: f64) {
-        self.camera.update(delta);
+    fn lookups(scene: &Scene) -> Vec<(Option<f64>, Color)> {
+        rays().iter().map(|ray| {
+            let distance = scene.nearest_hit(ray).map(|(_, hit)| hit.distance);
+            let point = ray.origin + ray.direction * 20.0;
+
+            (distance, scene.shadow(&point, &Vec3::new(0, -10, 0)))
+        }).collect()
+    }
+
+    #[test]
+    fn bvh_matches_testing_every_shape() {
+        let mut scene = scene();
+        assert!(!scene.bvh_is_current());
+        let linear = lookups(&scene);
+
+        scene.build_bvh();
+        assert!(scene.bvh_is_current());
+        assert_eq!(lookups(&scene), linear);
+        assert!(linear.iter().any(|(distance, _)| distance.is_some()) && linear.iter().any(|&(_, shadow)| shadow == Color::BLACK));
+
+        // moving a shape makes the BVH stale, so lookups go back to testing everything rather than missing it
+        *scene.shapes_mut()[0].location_mut().unwrap() = Vec3::new(0, 0, 2);
+        let moved = lookups(&scene);
+        assert_ne!(moved, linear);
+
+        scene.build_bvh();
+        assert_eq!(lookups(&scene), moved);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn transparent_shapes_let_light_through() {
+        let mut scene = Scene::new(Camera::new(Vec3::O, Vec3::K, FieldOfView::Horizontal(90.0)), Color::WHITE);
+        let glass = Finish { transmit: 0.5, ..Finish::DEFAULT };
+        scene.add_shape(Box::new(Sphere::new(Vec3::new(0, 0, 5), 1.0, Appearance::new(Color::WHITE, glass))));
+        scene.build_bvh();
+
+        // half gets through on the way in, and half of that on the way out
+        assert_eq!(scene.shadow(&Vec3::O, &Vec3::new(0, 0, 10)), Color::new(0.25, 0.25, 0.25));
+        assert_eq!(scene.shadow(&Vec3::O, &Vec3::new(0, 10, 0)), Color::WHITE);
+
+        // coloured glass tints what passes through it by how far the light went inside
+        scene.shapes_mut()[0].appearance_mut().unwrap().material = Color::new(1, 0, 0);
+        scene.shapes_mut()[0].appearance_mut().unwrap().finish.absorb = 1.0;
+        let tinted = scene.shadow(&Vec3::O, &Vec3::new(0, 0, 10));
+        assert_eq!(tinted.r, 0.25);
+        assert!((tinted.g - 0.25 * (-2.0f64).exp()).abs() < 1e-12);
+
+        // and opaque shapes still block it all
+        scene.shapes_mut()[0].appearance_mut().unwrap().finish = Finish::DEFAULT;
+        assert_eq!(scene.shadow(&Vec3::O, &Vec3::new(0, 0, 10)), Color::BLACK);
+    }
+}
@@ -0,0 +1,881 @@
+use std::{collections::HashMap, fmt, fs, ops::Range, path::{Path, PathBuf}, sync::Arc};
+
+use serde::{Deserialize, Deserializer, de::{Error, MapAccess, Visitor, value::MapAccessDeserializer}};
+use toml::Spanned;
+
+use super::{
+    error, scene::Scene, shape::Shape, camera::{Camera, FieldOfView, Projection}, vec3::Vec3, light::Light, appearance::Appearance, finish::Finish,
+    color::{Color, color_from_hex}, sphere::Sphere, plane::Plane, prism::Prism, mesh::{ColoredMesh, MeshAsset},
+    matrix::Transform, transformed::Transformed, instance::MeshInstance, csg::{Csg, CsgOperation},
+    keyframe::{Interpolate, Interpolation, Keyframe, Track}, animation::{Animation, FinishParameter, TransformTrack}, animate::Animate
+};
+
+// Declarative description of a scene, as read from a TOML file. Everything in here
+// gets turned into the regular structs by `build`; none of it is used while rendering.
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraDesc,
+    // name -> OBJ path, for meshes placed with `type = "instance"`
+    #[serde(default)]
+    assets: HashMap<String, PathBuf>,
+    #[serde(default = "default_background")]
+    background: HexColor,
+    #[serde(default)]
+    shapes: Vec<ShapeDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+    animate: Option<SceneAnimationDesc>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    location: Point,
+    look_at: Point,
+    // horizontal or vertical field of view in degrees; at most one of these and `width`
+    fov: Option<Spanned<f64>>,
+    vfov: Option<Spanned<f64>>,
+    // older scenes give the film size two units in front of the camera instead
+    width: Option<Spanned<f64>>,
+    // only a default; the renderer resizes the film to the output's aspect ratio
+    height: Option<f64>,
+    up: Option<Point>,
+    #[serde(default)]
+    roll: f64,
+    projection: Option<ProjectionDesc>,
+    // lens radius in scene units, or an f-number taking scene units as metres; at most one of these
+    aperture: Option<Spanned<f64>>,
+    f_stop: Option<Spanned<f64>>,
+    // what's in focus, as a distance or a point; `look_at` if neither
+    focus_distance: Option<Spanned<f64>>,
+    focus_on: Option<Spanned<Point>>,
+    lens_samples: Option<usize>,
+    #[serde(default)]
+    moves: Vec<CameraMoveDesc>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraMoveDesc {
+    to: Point,
+    duration: f64,
+    look_at: Option<Point>
+}
+
+enum ShapeDesc {
+    Sphere(SphereDesc),
+    Plane(PlaneDesc),
+    Prism(PrismDesc),
+    Mesh(MeshDesc),
+    Instance(InstanceDesc),
+    Csg(CsgOperation, CsgDesc)
+}
+
+const SHAPE_TYPES: &[&str] = &["sphere", "plane", "prism", "mesh", "instance", "union", "intersection", "difference"];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SphereDesc {
+    center: Point,
+    radius: f64,
+    appearance: AppearanceDesc,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PlaneDesc {
+    point: Point,
+    normal: Point,
+    appearance: AppearanceDesc,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PrismDesc {
+    corner_a: Point,
+    corner_b: Point,
+    appearance: AppearanceDesc,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MeshDesc {
+    path: Spanned<PathBuf>,
+    location: Option<Point>,
+    appearance: AppearanceDesc,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDesc {
+    asset: Spanned<String>,
+    appearance: AppearanceDesc,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+// Shared by `union`, `intersection` and `difference`. Each operand keeps its own appearance.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CsgDesc {
+    shapes: Spanned<Vec<ShapeDesc>>,
+    transform: Option<TransformDesc>,
+    animate: Option<Spanned<ShapeAnimationDesc>>
+}
+
+// serde's internally-tagged enums buffer the whole table before picking a variant, which throws
+// away the positions toml needs to point at the offending key. Reading `type` up front and then
+// handing the rest of the map straight to the variant keeps those positions intact.
+impl<'de> Deserialize<'de> for ShapeDesc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_map(ShapeVisitor)
+    }
+}
+
+struct ShapeVisitor;
+
+impl<'de> Visitor<'de> for ShapeVisitor {
+    type Value = ShapeDesc;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a shape table whose first key is `type`")
+    }
+
+    fn visit_map<A>(self, mut map: A) -> Result<Self::Value, A::Error>
+        where A: MapAccess<'de>
+    {
+        match map.next_key::<String>()? {
+            Some(key) if key == "type" => (),
+            Some(key) => return Err(A::Error::custom(format!("`type` must be the first key of a shape, found `{}`", key))),
+            None => return Err(A::Error::missing_field("type"))
+        }
+
+        let kind: String = map.next_value()?;
+        let rest = MapAccessDeserializer::new(map);
+
+        match kind.as_str() {
+            "sphere" => SphereDesc::deserialize(rest).map(ShapeDesc::Sphere),
+            "plane" => PlaneDesc::deserialize(rest).map(ShapeDesc::Plane),
+            "prism" => PrismDesc::deserialize(rest).map(ShapeDesc::Prism),
+            "mesh" => MeshDesc::deserialize(rest).map(ShapeDesc::Mesh),
+            "instance" => InstanceDesc::deserialize(rest).map(ShapeDesc::Instance),
+            "union" => CsgDesc::deserialize(rest).map(|desc| ShapeDesc::Csg(CsgOperation::Union, desc)),
+            "intersection" => CsgDesc::deserialize(rest).map(|desc| ShapeDesc::Csg(CsgOperation::Intersection, desc)),
+            "difference" => CsgDesc::deserialize(rest).map(|desc| ShapeDesc::Csg(CsgOperation::Difference, desc)),
+            other => Err(A::Error::unknown_variant(other, SHAPE_TYPES))
+        }
+    }
+}
+
+// Applied as scale, then rotation (about x, then y, then z, in degrees), then translation
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformDesc {
+    scale: Option<Spanned<ScaleDesc>>,
+    rotate: Option<Point>,
+    translate: Option<Point>
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes(Point)
+}
+
+impl ScaleDesc {
+    fn factors(self) -> Vec3 {
+        match self {
+            ScaleDesc::Uniform(factor) => Vec3::new(factor, factor, factor),
+            ScaleDesc::Axes(Point(factors)) => factors
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AppearanceDesc {
+    color: HexColor,
+    #[serde(default)]
+    finish: FinishDesc
+}
+
+#[derive(Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FinishDesc {
+    ambient: f64,
+    diffuse: f64,
+    shiny: f64,
+    reflect: f64,
+    transmit: f64,
+    ior: f64,
+    absorb: f64
+}
+
+impl Default for FinishDesc {
+    fn default() -> Self {
+        let Finish { ambient, diffuse, shiny, reflect, transmit, ior, absorb } = Finish::DEFAULT;
+        Self { ambient, diffuse, shiny, reflect, transmit, ior, absorb }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightDesc {
+    position: Point,
+    #[serde(default = "default_light_color")]
+    color: HexColor,
+    // multiplies `color`, for lights brighter than white
+    #[serde(default = "default_light_intensity")]
+    intensity: f64,
+    animate: Option<LightAnimationDesc>
+}
+
+// Tracks, each a list of `{ time, value, interpolation }` keys, with the interpolation shaping the
+// way to the next key (linear if left out)
+type TrackDesc<V> = Spanned<Vec<KeyDesc<V>>>;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyDesc<V> {
+    time: f64,
+    value: V,
+    interpolation: Option<InterpolationDesc>
+}
+
+// A name like "ease-in-out", or `{ bezier = [x1, y1, x2, y2] }`
+struct InterpolationDesc(Interpolation);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawInterpolation {
+    Named(String),
+    Bezier { bezier: [f64; 4] }
+}
+
+impl<'de> Deserialize<'de> for InterpolationDesc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        match RawInterpolation::deserialize(deserializer)? {
+            RawInterpolation::Named(name) => name.parse().map(InterpolationDesc).map_err(D::Error::custom),
+            RawInterpolation::Bezier { bezier: [x1, y1, x2, y2] } if (0.0..=1.0).contains(&x1) && (0.0..=1.0).contains(&x2) =>
+                Ok(InterpolationDesc(Interpolation::Bezier(x1, y1, x2, y2))),
+            RawInterpolation::Bezier { .. } => Err(D::Error::custom("a bezier's x1 and x2 must be between 0 and 1"))
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneAnimationDesc {
+    background: Option<TrackDesc<HexColor>>
+}
+
+// Only top-level shapes can be animated, not the operands of a CSG node
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShapeAnimationDesc {
+    // a sphere's `center`, a mesh's `location` or a plane's `point`
+    location: Option<TrackDesc<Point>>,
+    // applied after the shape's own `transform`
+    transform: Option<TransformAnimationDesc>,
+    color: Option<TrackDesc<HexColor>>,
+    finish: Option<FinishAnimationDesc>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransformAnimationDesc {
+    scale: Option<TrackDesc<ScaleDesc>>,
+    rotate: Option<TrackDesc<Point>>,
+    translate: Option<TrackDesc<Point>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FinishAnimationDesc {
+    ambient: Option<TrackDesc<f64>>,
+    diffuse: Option<TrackDesc<f64>>,
+    shiny: Option<TrackDesc<f64>>,
+    reflect: Option<TrackDesc<f64>>,
+    transmit: Option<TrackDesc<f64>>,
+    ior: Option<TrackDesc<f64>>,
+    absorb: Option<TrackDesc<f64>>
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LightAnimationDesc {
+    position: Option<TrackDesc<Point>>,
+    // multiplied by the light's `intensity`, like its `color`
+    color: Option<TrackDesc<HexColor>>
+}
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(from = "[f64; 3]")]
+struct Point(Vec3);
+
+impl From<[f64; 3]> for Point {
+    fn from(value: [f64; 3]) -> Self {
+        Point(Vec3::from(&value))
+    }
+}
+
+#[derive(Clone, Copy)]
+struct HexColor(Color);
+
+impl<'de> Deserialize<'de> for HexColor {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        let hex = String::deserialize(deserializer)?;
+        color_from_hex(&hex).map(HexColor).map_err(D::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct ProjectionDesc(Projection);
+
+impl<'de> Deserialize<'de> for ProjectionDesc {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map(ProjectionDesc).map_err(D::Error::custom)
+    }
+}
+
+fn default_background() -> HexColor {
+    HexColor(Color::BLACK)
+}
+
+fn default_light_color() -> HexColor {
+    HexColor(Color::WHITE)
+}
+
+fn default_light_intensity() -> f64 {
+    1.0
+}
+
+impl AppearanceDesc {
+    fn build(&self) -> Appearance {
+        let FinishDesc { ambient, diffuse, shiny, reflect, transmit, ior, absorb } = self.finish;
+        let finish = Finish::new(ambient, diffuse, shiny, reflect).with_transmission(transmit, ior).with_absorption(absorb);
+
+        Appearance::new(self.color.0, finish)
+    }
+}
+
+impl CameraDesc {
+    const DEFAULT_FOV: f64 = 90.0;
+
+    fn build(&self) -> Result<Camera, BuildError> {
+        let (location, look_at) = (self.location.0, self.look_at.0);
+        let given: Vec<&Spanned<f64>> = [&self.fov, &self.vfov, &self.width].into_iter().flatten().collect();
+
+        if let Some(extra) = given.get(1) {
+            return Err(BuildError { span: extra.span(), message: "give only one of `fov`, `vfov` or `width`".to_string() });
+        }
+
+        // at 180 degrees a perspective picture would be infinitely wide
+        for (name, angle) in [("fov", &self.fov), ("vfov", &self.vfov)] {
+            if let Some(angle) = angle.as_ref().filter(|angle| !(*angle.get_ref() > 0.0 && *angle.get_ref() < 180.0)) {
+                return Err(BuildError { span: angle.span(), message: format!("`{}` must be between 0 and 180 degrees", name) });
+            }
+        }
+
+        let mut camera = match (&self.fov, &self.vfov, &self.width) {
+            (_, Some(vfov), _) => Camera::new(location, look_at, FieldOfView::Vertical(*vfov.get_ref())),
+            (_, _, Some(width)) => {
+                let width = *width.get_ref();
+                Camera::from_film(location, look_at, width, self.height.unwrap_or(width * 9.0 / 16.0))
+            },
+            (fov, _, _) => Camera::new(location, look_at, FieldOfView::Horizontal(fov.as_ref().map_or(Self::DEFAULT_FOV, |fov| *fov.get_ref())))
+        };
+
+        if let Some(Point(up)) = self.up {
+            camera = camera.with_up(up);
+        }
+
+        if let Some(ProjectionDesc(projection)) = self.projection {
+            camera = camera.with_projection(projection);
+        }
+
+        camera = camera.with_roll(self.roll);
+
+        match (&self.aperture, &self.f_stop) {
+            (Some(_), Some(f_stop)) => return Err(BuildError { span: f_stop.span(), message: "give only one of `aperture` or `f_stop`".to_string() }),
+            (Some(aperture), None) => camera = camera.with_aperture(*aperture.get_ref()),
+            (None, Some(f_stop)) if *f_stop.get_ref() <= 0.0 => return Err(BuildError { span: f_stop.span(), message: "`f_stop` must be positive".to_string() }),
+            (None, Some(f_stop)) => camera = camera.with_f_stop(*f_stop.get_ref()),
+            (None, None) => ()
+        }
+
+        match (&self.focus_distance, &self.focus_on) {
+            (Some(_), Some(point)) => return Err(BuildError { span: point.span(), message: "give only one of `focus_distance` or `focus_on`".to_string() }),
+            (Some(distance), None) => camera = camera.with_focus_distance(*distance.get_ref()),
+            (None, Some(point)) => camera = camera.focus_on(&point.get_ref().0),
+            (None, None) => ()
+        }
+
+        if let Some(samples) = self.lens_samples {
+            camera = camera.with_lens_samples(samples);
+        }
+
+        Ok(camera)
+    }
+}
+
+impl TransformDesc {
+    fn build(&self) -> Result<Transform, BuildError> {
+        let scale = match &self.scale {
+            Some(scale) if has_zero(scale.get_ref().factors()) => return Err(zero_scale(scale.span())),
+            Some(scale) => scale.get_ref().factors(),
+            None => Vec3::new(1, 1, 1)
+        };
+
+        Ok(Transform::compose(scale, self.rotate.map_or(Vec3::O, |p| p.0), self.translate.map_or(Vec3::O, |p| p.0)))
+    }
+}
+
+// A scale of 0 squashes a shape flat for good: the transform has no inverse, so rays can't be taken back into the shape
+fn has_zero(factors: Vec3) -> bool {
+    (0..3).any(|axis| factors[axis] == 0.0)
+}
+
+fn zero_scale(span: Range<usize>) -> BuildError {
+    BuildError { span, message: "scale factors can't be 0".to_string() }
+}
+
+// Keys can't be 0 either, and nor can anything between them: a factor that changes sign on the way from one key to the next
+// passes through 0, unless it steps straight across
+fn check_scale_track(track: &Track<Vec3>, span: Range<usize>) -> Result<(), BuildError> {
+    let keys = track.keys();
+    let crosses_zero = |from: &Keyframe<Vec3>, to: &Keyframe<Vec3>| {
+        from.interpolation != Interpolation::Step && (0..3).any(|axis| from.value[axis].signum() != to.value[axis].signum())
+    };
+
+    if keys.iter().any(|key| has_zero(key.value)) || keys.windows(2).any(|pair| crosses_zero(&pair[0], &pair[1])) {
+        return Err(BuildError { span, message: "scale factors can't be 0, or change sign between keys".to_string() });
+    }
+
+    Ok(())
+}
+
+fn track<V, T>(desc: TrackDesc<V>, value: impl Fn(V) -> T) -> Result<Track<T>, BuildError>
+    where T: Interpolate
+{
+    let span = desc.span();
+    let keys = desc.into_inner();
+
+    if keys.is_empty() {
+        return Err(BuildError { span, message: "needs at least one key".to_string() });
+    }
+
+    Ok(keys.into_iter().fold(Track::new(), |track, key| {
+        track.key(key.time, value(key.value), key.interpolation.map_or(Interpolation::Linear, |i| i.0))
+    }))
+}
+
+impl ShapeDesc {
+    fn take_animation(&mut self) -> Option<Spanned<ShapeAnimationDesc>> {
+        match self {
+            ShapeDesc::Sphere(SphereDesc { animate, .. }) |
+            ShapeDesc::Plane(PlaneDesc { animate, .. }) |
+            ShapeDesc::Prism(PrismDesc { animate, .. }) |
+            ShapeDesc::Mesh(MeshDesc { animate, .. }) |
+            ShapeDesc::Instance(InstanceDesc { animate, .. }) |
+            ShapeDesc::Csg(_, CsgDesc { animate, .. }) => animate.take()
+        }
+    }
+}
+
+impl ShapeAnimationDesc {
+    // The tracks for the shape at `index`, which may get wrapped in a `Transformed` so that it has a transform to animate
+    fn build<'a>(self, span: Range<usize>, index: usize, mut shape: Box<dyn Shape + Send + Sync + 'a>, animations: &mut Vec<Animation>)
+        -> Result<Box<dyn Shape + Send + Sync + 'a>, BuildError>
+    {
+        if let Some(location) = self.location {
+            if shape.location_mut().is_none() {
+                return Err(BuildError { span: location.span(), message: "this shape has no location to animate; animate its `transform` instead".to_string() });
+            }
+
+            animations.push(Animation::ShapeLocation(index, track(location, |p| p.0)?));
+        }
+
+        if let Some(TransformAnimationDesc { scale, rotate, translate }) = self.transform {
+            if shape.transform_mut().is_none() {
+                shape = Box::new(Transformed::new(shape, Transform::IDENTITY));
+            }
+
+            let mut transform = TransformTrack::new(*shape.transform_mut().expect("transformed shapes always have a transform"));
+
+            if let Some(scale) = scale {
+                let span = scale.span();
+                transform.scale = track(scale, ScaleDesc::factors)?;
+                check_scale_track(&transform.scale, span)?;
+            }
+
+            if let Some(rotate) = rotate { transform.rotate = track(rotate, |p| p.0)?; }
+            if let Some(translate) = translate { transform.translate = track(translate, |p| p.0)?; }
+
+            animations.push(Animation::ShapeTransform(index, Box::new(transform)));
+        }
+
+        if (self.color.is_some() || self.finish.is_some()) && shape.appearance_mut().is_none() {
+            return Err(BuildError { span, message: "a CSG node has no appearance of its own; animate its shapes' instead".to_string() });
+        }
+
+        if let Some(color) = self.color {
+            animations.push(Animation::ShapeColor(index, track(color, |c| c.0)?));
+        }
+
+        if let Some(FinishAnimationDesc { ambient, diffuse, shiny, reflect, transmit, ior, absorb }) = self.finish {
+            let parameters = [
+                (FinishParameter::Ambient, ambient), (FinishParameter::Diffuse, diffuse), (FinishParameter::Shiny, shiny),
+                (FinishParameter::Reflect, reflect), (FinishParameter::Transmit, transmit), (FinishParameter::Ior, ior),
+                (FinishParameter::Absorb, absorb)
+            ];
+
+            for (parameter, desc) in parameters {
+                if let Some(desc) = desc {
+                    animations.push(Animation::ShapeFinish(index, parameter, track(desc, |v| v)?));
+                }
+            }
+        }
+
+        Ok(shape)
+    }
+}
+
+// Problems found after parsing, while turning descriptions into shapes. Carries the span of the
+// offending value so it can be reported the same way as a parse error.
+struct BuildError {
+    span: Range<usize>,
+    message: String
+}
+
+// State shared while building one scene file's shapes
+struct Builder<'p> {
+    base_dir: &'p Path,
+    // every OBJ file is only loaded once, however many shapes use it
+    meshes: HashMap<PathBuf, Arc<MeshAsset>>,
+    assets: HashMap<String, PathBuf>
+}
+
+impl Builder<'_> {
+    // `span` is what to point at if the file can't be loaded
+    fn mesh(&mut self, path: &Path, span: Range<usize>) -> Result<Arc<MeshAsset>, BuildError> {
+        // mesh paths are relative to the scene file, not to wherever we were launched from
+        let path = self.base_dir.join(path);
+
+        if let Some(mesh) = self.meshes.get(&path) {
+            return Ok(mesh.clone());
+        }
+
+        let mesh = MeshAsset::load(&path).map_err(|err| BuildError { span, message: err.to_string() })?;
+        self.meshes.insert(path, mesh.clone());
+        Ok(mesh)
+    }
+
+    fn shape<'a>(&mut self, desc: ShapeDesc) -> Result<Box<dyn Shape + Send + Sync + 'a>, BuildError> {
+        let (shape, transform): (Box<dyn Shape + Send + Sync + 'a>, _) = match desc {
+            ShapeDesc::Sphere(SphereDesc { center, radius, appearance, transform, .. }) =>
+                (Box::new(Sphere::new(center.0, radius, appearance.build())), transform),
+            ShapeDesc::Plane(PlaneDesc { point, normal, appearance, transform, .. }) =>
+                (Box::new(Plane::new(point.0, normal.0, appearance.build())), transform),
+            ShapeDesc::Prism(PrismDesc { corner_a, corner_b, appearance, transform, .. }) =>
+                (Box::new(Prism::new(corner_a.0, corner_b.0, appearance.build())), transform),
+            ShapeDesc::Mesh(MeshDesc { path, location, appearance, transform, .. }) => {
+                let location = location.map_or(Vec3::O, |p| p.0);
+                (Box::new(ColoredMesh::from_asset(self.mesh(path.get_ref(), path.span())?, location, appearance.build())), transform)
+            },
+            ShapeDesc::Instance(InstanceDesc { asset, appearance, transform, .. }) => {
+                let Some(path) = self.assets.get(asset.get_ref()).cloned() else {
+                    return Err(BuildError { span: asset.span(), message: format!("unknown asset `{}`", asset.get_ref()) });
+                };
+
+                // instances carry their transform themselves rather than through a Transformed wrapper
+                let transform = transform.map_or(Ok(Transform::IDENTITY), |t| t.build())?;
+                return Ok(Box::new(MeshInstance::new(self.mesh(&path, asset.span())?, transform, appearance.build())));
+            },
+            ShapeDesc::Csg(operation, CsgDesc { shapes, transform, .. }) => {
+                if shapes.get_ref().len() < 2 {
+                    return Err(BuildError { span: shapes.span(), message: "needs at least two shapes".to_string() });
+                }
+
+                let children = shapes.into_inner().into_iter()
+                    .map(|mut child| match child.take_animation() {
+                        Some(animate) => Err(BuildError { span: animate.span(), message: "only top-level shapes can be animated".to_string() }),
+                        None => self.shape(child)
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                (Box::new(Csg::new(operation, children)), transform)
+            }
+        };
+
+        Ok(match transform {
+            Some(transform) => Box::new(Transformed::new(shape, transform.build()?)),
+            None => shape
+        })
+    }
+}
+
+impl SceneDesc {
+    fn build<'a>(self, base_dir: &Path) -> Result<Scene<'a>, BuildError> {
+        let mut camera = self.camera.build()?;
+
+        for camera_move in self.camera.moves {
+            camera.add_camera_move(camera_move.to.0, camera_move.duration, camera_move.look_at.map(|p| p.0));
+        }
+
+        let mut scene = Scene::new(camera, self.background.0);
+        let mut builder = Builder { base_dir, meshes: HashMap::new(), assets: self.assets };
+
+        for mut desc in self.shapes {
+            let animate = desc.take_animation();
+            let mut shape = builder.shape(desc)?;
+
+            if let Some(animate) = animate {
+                let span = animate.span();
+                shape = animate.into_inner().build(span, scene.shapes().len(), shape, &mut scene.animations)?;
+            }
+
+            scene.add_shape(shape);
+        }
+
+        for light in self.lights {
+            let (index, intensity) = (scene.lights.len(), light.intensity);
+            scene.lights.push(Light::new(light.position.0, light.color.0 * intensity));
+
+            if let Some(LightAnimationDesc { position, color }) = light.animate {
+                if let Some(position) = position {
+                    scene.animations.push(Animation::LightPosition(index, track(position, |p| p.0)?));
+                }
+
+                if let Some(color) = color {
+                    scene.animations.push(Animation::LightColor(index, track(color, |c| c.0 * intensity)?));
+                }
+            }
+        }
+
+        if let Some(SceneAnimationDesc { background: Some(background) }) = self.animate {
+            scene.animations.push(Animation::Background(track(background, |c| c.0)?));
+        }
+
+        // starting out at the beginning of the animation, and with a BVH that fits
+        scene.set_time(0.0);
+        scene.build_bvh();
+        Ok(scene)
+    }
+}
+
+/// Reads and builds the scene described by the TOML file at `path`.
+pub fn load_scene<'a>(path: &Path) -> error::Result<Scene<'a>> {
+    let source = fs::read_to_string(path).map_err(|source| error::Error::Read { path: path.to_path_buf(), source })?;
+    let base_dir = path.parent().unwrap_or(Path::new("."));
+
+    parse_scene(&source, base_dir).map_err(|err| match err {
+        error::Error::Scene { position, message, .. } => error::Error::Scene { file: Some(path.to_path_buf()), position, message },
+        other => other
+    })
+}
+
+/// Builds a scene from TOML source. Errors come back as `Error::Scene`, pointing at the line and
+/// column of the offending value; `load_scene` fills in the filename.
+pub fn parse_scene<'a>(source: &str, base_dir: &Path) -> error::Result<Scene<'a>> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|err| match err.span() {
+        Some(span) => describe_error(source, span, err.message().trim()),
+        None => error::Error::Scene { file: None, position: None, message: err.message().trim().to_string() }
+    })?;
+
+    desc.build(base_dir).map_err(|err| describe_error(source, err.span, &err.message))
+}
+
+fn describe_error(source: &str, span: Range<usize>, message: &str) -> error::Error {
+    let before = &source[..span.start];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1) + 1;
+
+    // The span points at the offending value (or table); the key is whatever sits left of the `=` on that line
+    let line_text = source.lines().nth(line - 1).unwrap_or("");
+    let message = match line_text.split_once('=') {
+        Some((key, _)) if column > key.len() => format!("{} (key `{}`)", message, key.trim()),
+        _ => format!("{} (at `{}`)", message, line_text.trim())
+    };
+
+    error::Error::Scene { file: None, position: Some((line, column)), message }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::structs::{scene_file::parse_scene, animate::Animate, ray::Ray, vec3::Vec3, color::Color};
+
+    const MINIMAL: &str = r##"
+        background = "#102030"
+
+        [camera]
+        location = [0, 0, -10]
+        look_at = [0, 0, 0]
+        width = 4.0
+        height = 2.25
+
+        [[shapes]]
+        type = "sphere"
+        center = [0, 0, 0]
+        radius = 1
+        appearance = { color = "#FF0000", finish = { diffuse = 0.5 } }
+
+        [[lights]]
+        position = [10, -10, -10]
+    "##;
+
+    #[test]
+    fn parses_minimal_scene() {
+        let scene = parse_scene(MINIMAL, Path::new(".")).unwrap();
+
+        assert_eq!(scene.background.to_srgb().0, [0x10, 0x20, 0x30, 0xFF]);
+        assert_eq!(scene.shapes().len(), 1);
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn reports_line_and_key() {
+        let source = MINIMAL.replace("radius = 1", "radius = \"big\"");
+        let message = parse_scene(&source, Path::new(".")).err().unwrap().to_string();
+
+        assert!(message.starts_with("13:"), "{}", message);
+        assert!(message.contains("radius"), "{}", message);
+    }
+
+    #[test]
+    fn reports_unknown_asset() {
+        let source = format!("{}\n[[shapes]]\ntype = \"instance\"\nasset = \"chair\"\nappearance = {{ color = \"#FFFFFF\" }}\n", MINIMAL);
+        let message = parse_scene(&source, Path::new(".")).err().unwrap().to_string();
+
+        assert!(message.contains("unknown asset `chair` (key `asset`)"), "{}", message);
+    }
+
+    #[test]
+    fn reports_missing_mesh() {
+        let source = format!("{}\n[[shapes]]\ntype = \"mesh\"\npath = \"missing.obj\"\nappearance = {{ color = \"#FFFFFF\" }}\n", MINIMAL);
+        let message = parse_scene(&source, Path::new(".")).err().unwrap().to_string();
+
+        assert!(message.contains("Unable to load mesh ./missing.obj"), "{}", message);
+        assert!(message.ends_with("(key `path`)"), "{}", message);
+    }
+
+    #[test]
+    fn parses_nested_csg() {
+        let source = format!(r##"{}
+            [[shapes]]
+            type = "difference"
+            transform = {{ translate = [0, 0, 3] }}
+
+            [[shapes.shapes]]
+            type = "prism"
+            corner_a = [-1, -1, -1]
+            corner_b = [1, 1, 1]
+            appearance = {{ color = "#FFFFFF" }}
+
+            [[shapes.shapes]]
+            type = "union"
+            shapes = [
+                {{ type = "sphere", center = [0, 0, -1], radius = 0.5, appearance = {{ color = "#00FF00" }} }},
+                {{ type = "sphere", center = [0, 0, 1], radius = 0.5, appearance = {{ color = "#0000FF" }} }}
+            ]
+        "##, MINIMAL);
+        let scene = parse_scene(&source, Path::new(".")).unwrap();
+
+        assert_eq!(scene.shapes().len(), 2);
+
+        let message = parse_scene(&source.replacen("type = \"union\"", "type = \"sphere\"", 1), Path::new(".")).err().unwrap().to_string();
+        assert!(message.contains("shapes"), "{}", message);
+    }
+
+    #[test]
+    fn animates_properties() {
+        let source = format!("{}{}", MINIMAL, r##"
+            [shapes.animate]
+            location = [{ time = 0, value = [0, 0, 0] }, { time = 2, value = [0, -10, 0], interpolation = "ease-in-out" }]
+            color = [{ time = 0, value = "#FF0000", interpolation = "step" }, { time = 1, value = "#0000FF" }]
+            finish = { reflect = [{ time = 0, value = 0 }, { time = 2, value = 1, interpolation = { bezier = [0.25, 0.25, 0.75, 0.75] } }] }
+
+            [lights.animate]
+            position = [{ time = 1, value = [0, 0, -10] }]
+
+            [animate]
+            background = [{ time = 0, value = "#000000" }, { time = 4, value = "#FFFFFF" }]
+        "##);
+        let mut scene = parse_scene(&source, Path::new(".")).unwrap();
+
+        // loaded as it is at the start
+        assert_eq!(scene.background, Color::BLACK);
+        assert_eq!(scene.lights[0].position, Vec3::new(0, 0, -10));
+
+        scene.set_time(1.0);
+        let hit = scene.nearest_hit(&Ray::new(Vec3::new(0, -5, -10), Vec3::K)).map(|(_, hit)| hit).unwrap();
+        assert_eq!(hit.point, Vec3::new(0, -5, -1));
+        assert_eq!(hit.appearance.material, Color::new(0, 0, 1));
+        assert!((hit.appearance.finish.reflect - 0.5).abs() < 1e-9);
+        assert_eq!(scene.background, Color::new(0.25, 0.25, 0.25));
+
+        // and the BVH keeps up as the sphere moves
+        scene.set_time(0.0);
+        assert!(scene.nearest_hit(&Ray::new(Vec3::new(0, -5, -10), Vec3::K)).is_none());
+    }
+
+    #[test]
+    fn reports_camera_mistakes() {
+        let with = |lines: &str| MINIMAL.replace("width = 4.0\n        height = 2.25", lines);
+        assert!(parse_scene(&with("fov = 179"), Path::new(".")).is_ok());
+
+        for (lines, expected) in [("fov = 180", "`fov` must be"), ("fov = -30", "`fov` must be"), ("vfov = 0", "`vfov` must be"), ("fov = 60\nvfov = 40", "give only one")] {
+            let message = parse_scene(&with(lines), Path::new(".")).err().unwrap().to_string();
+            assert!(message.contains(expected), "{}", message);
+            assert!(message.contains("fov"), "{}", message);
+        }
+    }
+
+    #[test]
+    fn reports_animation_mistakes() {
+        let csg = r##"
+            [[shapes]]
+            type = "union"
+            shapes = [
+                { type = "sphere", center = [0, 0, 0], radius = 1, appearance = { color = "#FFFFFF" } },
+                { type = "sphere", center = [1, 0, 0], radius = 1, appearance = { color = "#FFFFFF" }, animate = { color = [] } }
+            ]
+        "##;
+
+        let cases = [
+            (format!("{}{}", MINIMAL, csg), "only top-level shapes can be animated"),
+            (MINIMAL.replace("radius = 1", "radius = 1\nanimate = { color = [] }"), "needs at least one key"),
+            (MINIMAL.replace("type = \"sphere\"", "type = \"prism\"").replace("center = [0, 0, 0]\n        radius = 1", "corner_a = [0, 0, 0]\ncorner_b = [1, 1, 1]\nanimate = { location = [{ time = 0, value = [0, 0, 0] }] }"), "no location to animate"),
+            (MINIMAL.replace("radius = 1", "radius = 1\nanimate = { color = [{ time = 0, value = \"#FFFFFF\", interpolation = \"bounce\" }] }"), "Unknown interpolation `bounce`"),
+            (MINIMAL.replace("radius = 1", "radius = 1\ntransform = { scale = [1, 0, 1] }"), "scale factors can't be 0"),
+            (MINIMAL.replace("radius = 1", "radius = 1\nanimate = { transform = { scale = [{ time = 0, value = 1 }, { time = 1, value = -1 }] } }"), "change sign between keys")
+        ];
+
+        for (source, expected) in cases {
+            let message = parse_scene(&source, Path::new(".")).err().unwrap().to_string();
+            assert!(message.contains(expected), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::{fs::File, io::BufReader, path::{Path, PathBuf}};
+
+use image::{codecs::hdr::HdrDecoder, Rgba};
+
+use super::{color::Color, error::{Error, Result}, hdr_output::{is_float_format, load_pfm}, tonemap::ToneMapper};
+
+/// A numbered filename for the frames of an animation, written printf-style: `frame_%04d.png`
+/// is `frame_0000.png`, `frame_0001.png` and so on, and a plain `%d` doesn't pad at all.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FramePattern {
+    prefix: String,
+    digits: usize,
+    suffix: String
+}
+
+impl FramePattern {
+    /// None unless `pattern` has exactly one `%d` or `%0Nd` in it.
+    pub fn parse(pattern: &str) -> Option<Self> {
+        let (prefix, rest) = pattern.split_once('%')?;
+        let (width, suffix) = rest.split_once('d')?;
+
+        let digits = match width {
+            "" => 0,
+            width if width.starts_with('0') => width.parse().ok()?,
+            _ => return None
+        };
+
+        if suffix.contains('%') {
+            return None;
+        }
+
+        Some(Self {
+            prefix: prefix.to_string(),
+            digits,
+            suffix: suffix.to_string()
+        })
+    }
+
+    pub fn path(&self, frame: usize) -> PathBuf {
+        PathBuf::from(format!("{}{:0width$}{}", self.prefix, frame, self.suffix, width = self.digits))
+    }
+
+    /// The frames already on disk, counting up from 0 (or from 1, if there's no frame 0) until the first one missing.
+    pub fn existing(&self) -> Vec<PathBuf> {
+        let first = if self.path(0).exists() { 0 } else { 1 };
+
+        (first..).map(|frame| self.path(frame)).take_while(|path| path.exists()).collect()
+    }
+}
+
+/// Reads one frame of a sequence back as display-ready pixels, along with its width and height.
+/// Floating-point frames (.hdr, .exr, .pfm) go through `tonemapper`; anything else is taken as it is.
+pub fn read_frame(path: &Path, tonemapper: &ToneMapper) -> Result<(usize, usize, Vec<Rgba<u8>>)> {
+    let read_error = |source| Error::ImageRead { path: path.to_path_buf(), source };
+    let tonemap = |pixels: Vec<[f32; 3]>| pixels.into_iter().map(|[r, g, b]| tonemapper.map(&Color::new(r, g, b))).collect();
+
+    let has_extension = |expected: &str| path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case(expected));
+
+    if has_extension("pfm") {
+        let (width, height, pixels) = load_pfm(path).map_err(|source| Error::Read { path: path.to_path_buf(), source })?;
+        return Ok((width, height, tonemap(pixels)));
+    }
+
+    if has_extension("hdr") {
+        // `image::open` would squash these down to 8 bits before we get to tone map them
+        let file = File::open(path).map_err(|source| Error::Read { path: path.to_path_buf(), source })?;
+        let decoder = HdrDecoder::new(BufReader::new(file)).map_err(read_error)?;
+        let (width, height) = (decoder.metadata().width as usize, decoder.metadata().height as usize);
+        let pixels = decoder.read_image_hdr().map_err(read_error)?;
+
+        return Ok((width, height, tonemap(pixels.into_iter().map(|p| p.0).collect())));
+    }
+
+    let image = image::open(path).map_err(read_error)?;
+    let (width, height) = (image.width() as usize, image.height() as usize);
+
+    if is_float_format(path) {
+        Ok((width, height, tonemap(image.into_rgb32f().pixels().map(|p| p.0).collect())))
+    } else {
+        Ok((width, height, image.into_rgba8().pixels().copied().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf};
+
+    use crate::structs::{sequence::{FramePattern, read_frame}, hdr_output::save_float, framebuffer::Framebuffer, tonemap::{ToneCurve, ToneMapper}, color::Color};
+
+    #[test]
+    fn frame_patterns() {
+        let pattern = FramePattern::parse("frames/frame_%04d.png").unwrap();
+        assert_eq!(pattern.path(7), PathBuf::from("frames/frame_0007.png"));
+        assert_eq!(pattern.path(12345), PathBuf::from("frames/frame_12345.png"));
+
+        assert_eq!(FramePattern::parse("%d.exr").unwrap().path(42), PathBuf::from("42.exr"));
+
+        for bad in ["img.png", "frame_%4d.png", "%d_%d.png", "frame_%s.png"] {
+            assert!(FramePattern::parse(bad).is_none(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn reads_back_float_frames() {
+        let mut framebuffer = Framebuffer::new(3, 2);
+        framebuffer.set(0, 0, Color::new(4.0, 0.5, 0.0), 1);
+        framebuffer.set(2, 1, Color::new(0.25, 0.75, 1.0), 1);
+
+        let tonemapper = ToneMapper::new(ToneCurve::Clamp, 0.0);
+
+        // PFM and EXR keep their floats exactly, so they come back as the same pixels as tone mapping the render directly
+        for ext in ["pfm", "exr"] {
+            let path = env::temp_dir().join(format!("eyebeam_reads_back_float_frames.{}", ext));
+            save_float(&framebuffer, &path).unwrap();
+
+            assert_eq!(read_frame(&path, &tonemapper).unwrap(), (3, 2, framebuffer.to_rgba(&tonemapper)), "{}", ext);
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -1,63 +1,110 @@
-use image::Rgba;
+use super::{ray::Ray, vec3::Vec3, scene::Scene, color::Color, hit::Hit, bvh::Aabb, matrix::Transform, appearance::Appearance};
 
-use super::{ray::Ray, vec3::Vec3, scene::Scene, color::color_add, appearance::Appearance};
+// Anything closer than this is treated as the surface the ray started from
+pub const SELF_HIT_THRESHOLD: f64 = 0.000001;
 
 pub trait Shape {
-    fn intersections(&self, _ray: &Ray) -> Vec<f64> {
-        panic!("Attempting to call intersections on a struct that hasn't implemented it")
+    /// Every place the ray's line crosses this shape's surface, in no particular order.
+    /// Hits behind the ray origin (negative distances) are allowed and are filtered out by `closest_hit`.
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>>;
+
+    fn closest_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        self.intersections(ray).into_iter()
+            .filter(|hit| hit.distance > SELF_HIT_THRESHOLD)                     // throw away anything less than this threshold
+            .reduce(|acc, cur| if cur.distance < acc.distance {cur} else {acc})  // get the min
     }
 
-    fn closest_distance_along_ray(&self, ray: &Ray) -> f64 {
-        *self.intersections(ray).iter()
-             .filter(|&&d| d > 0.000001)                        // throw away anything less than this threshold
-             .reduce(|acc, cur| if cur < acc {cur} else {acc})  // get the min
-             .unwrap_or(&f64::INFINITY)                         // if there were no intersections, return inf so the background is rendered
+    /// World-space bounding box, used to place the shape in the scene's BVH.
+    /// Shapes that go on forever (planes) return None and get tested against every ray instead.
+    fn bounds(&self) -> Option<Aabb> {
+        None
     }
 
-    fn normal_at(&self, _point: &Vec3, _ray: &Ray) -> Vec3 {
-        Vec3::O
+    /// The point a location track moves: a sphere's centre, a mesh's location or a plane's point.
+    /// None for shapes without one, which can still be moved by animating a transform.
+    fn location_mut(&mut self) -> Option<&mut Vec3> {
+        None
     }
 
-    fn appearance(&self) -> Appearance {
-        panic!("No default appearance for base Shape")
+    /// The transform of a transformed shape or a mesh instance, for transform tracks.
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        None
     }
 
-    fn color_at(&self, point: &Vec3, ray: &Ray, scene: &Scene, depth: i32) -> Rgba<u8> {
-        let normal = self.normal_at(point, ray);
-        let mut color = self.appearance().ambient_color_at(point);
-        let reflex = ray.reflect(&normal);
-        let reflection =  self.appearance().reflect(point, &reflex, scene, depth);
+    /// For colour and finish tracks. None for CSG nodes, whose parts each keep their own.
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        None
+    }
 
-        color = color_add(&color, &reflection);
+    fn color_at(&self, hit: &Hit, ray: &Ray, scene: &Scene, depth: i32) -> Color {
+        let normal = hit.facing_normal();
+        let point = &hit.point;
+        let appearance = hit.appearance;
+        let mut color = appearance.ambient_color_at(hit);
+        let reflex = ray.reflect(&normal);
+        let reflection = appearance.reflect(hit, &reflex, scene, depth);
+        let transmission = appearance.transmit(hit, ray, &reflex, scene, depth);
 
-        // point / vector calculations seem to be correct -- the issue might be in illuminate?
+        color += reflection;
+        color += transmission;
 
         for light in &scene.lights {
             let v = Vec3::between(point, &light.position);
 
             // If this shape is in another shape's shadow, stop the calculation here
-            if scene.shapes.iter().any(|shape| shape.casts_shadow(point, v)) { continue; }
+            let shadow = scene.shadow(point, &light.position);
+            if shadow == Color::BLACK { continue; }
 
             let brightness = normal.dot(&v.unit());
 
             if brightness <= 0.0 { continue; }
 
-            let illumination = light.illuminate(self.appearance().diffuse_color_at(point), *point, brightness);
+            let illumination = light.illuminate(appearance.diffuse_color_at(hit), *point, brightness);
 
-            color = color_add(&color, &illumination);
+            color += illumination * shadow;
 
-            let highlight = self.appearance().finish.add_highlight(&reflex, light, &v);
+            let highlight = appearance.finish.add_highlight(&reflex, light, &v);
 
-            color = color_add(&color, &highlight);
+            color += highlight * shadow;
         }
 
-        color
+        appearance.absorb(hit, &color)
     }
 
-    fn casts_shadow(&self, point: &Vec3, light_vector: Vec3) -> bool {
-        let distance_to_light = Vec3::between(point, &light_vector).length();
+    /// How much light from `light_position` gets past this shape to `point`: white if it's not in the way,
+    /// black if it's opaque, and whatever transparent surfaces let through otherwise.
+    fn shadow(&self, point: &Vec3, light_position: &Vec3) -> Color {
+        let light_vector = Vec3::between(point, light_position);
         let ray = Ray::new(*point, light_vector);
+        let distance_to_light = light_vector.length();
+
+        // most shapes are opaque, and the first surface in the way settles it
+        match self.closest_hit(&ray) {
+            Some(hit) if hit.distance <= distance_to_light => if hit.appearance.finish.transmit <= 0.0 { return Color::BLACK; },
+            _ => return Color::WHITE
+        }
+
+        let mut hits: Vec<Hit> = self.intersections(&ray).into_iter()
+            .filter(|hit| hit.distance > SELF_HIT_THRESHOLD && hit.distance <= distance_to_light)
+            .collect();
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+
+        let mut light = Color::WHITE;
+        // where the ray last went into the shape; a ray that starts out leaving it began inside
+        let mut entered = 0.0;
+
+        for hit in hits {
+            if hit.appearance.finish.transmit <= 0.0 {
+                return Color::BLACK;
+            }
+
+            light = light * hit.appearance.transmittance(&hit, hit.distance - entered);
+
+            if hit.front_face {
+                entered = hit.distance;
+            }
+        }
 
-        self.closest_distance_along_ray(&ray) <= distance_to_light
+        light
     }
-}
\ No newline at end of file
+}
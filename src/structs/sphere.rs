@@ -1,4 +1,6 @@
-use super::{shape::Shape, vec3::Vec3, ray::Ray, appearance::Appearance};
+use std::f64::consts::PI;
+
+use super::{shape::Shape, vec3::Vec3, ray::Ray, appearance::Appearance, bvh::Aabb, hit::Hit};
 
 pub struct Sphere { 
     pub center: Vec3,
@@ -16,31 +18,49 @@ impl Sphere {
             appearance
         }
     }
+
+    fn hit_at(&self, ray: &Ray, distance: f64) -> Hit<'_> {
+        let point = ray.origin + ray.direction * distance;
+        let normal = Vec3::between(&self.center, &point).unit();
+
+        // longitude/latitude, with v = 0 at the -y pole
+        let u = 0.5 + normal.z.atan2(normal.x) / (2.0 * PI);
+        let v = (-normal.y).clamp(-1.0, 1.0).acos() / PI;
+
+        Hit::new(ray, distance, normal, &self.appearance).with_uv(u, v)
+    }
 }
 
 impl Shape for Sphere {
-    fn intersections(&self, ray: &Ray) -> Vec<f64> {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
         // don't ask me what this does lol
         let os = Vec3::between(&self.center, &ray.origin);
         let b = 2.0 * os.dot(&ray.direction);
         let c = os.squid() - self.radius * self.radius;
         let discriminant = b * b - 4.0 * c;
 
-        if discriminant < 0.0 {
+        let distances = if discriminant < 0.0 {
             vec![]
         } else if discriminant.abs() < f64::EPSILON {
             vec![-b / 2.0]
         } else {
             let root = discriminant.sqrt();
             vec![(-b - root) / 2.0, (-b + root) / 2.0]
-        }
+        };
+
+        distances.into_iter().map(|distance| self.hit_at(ray, distance)).collect()
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        Some(Aabb::new(self.center - r, self.center + r))
     }
 
-    fn appearance(&self) -> Appearance {
-        self.appearance.clone()
+    fn location_mut(&mut self) -> Option<&mut Vec3> {
+        Some(&mut self.center)
     }
 
-    fn normal_at(&self, point: &Vec3, _ray: &Ray) -> Vec3 {
-        (Vec3::between(point, &self.center)).unit().invert()
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        Some(&mut self.appearance)
     }
 }
\ No newline at end of file
@@ -0,0 +1,99 @@
+use std::str::FromStr;
+
+use image::Rgba;
+
+use super::color::Color;
+
+/// How radiance past 1.0 gets squeezed into the displayable range.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneCurve {
+    // no curve at all; anything too bright just clips
+    Clamp,
+    Reinhard,
+    // John Hable's Uncharted 2 curve
+    Filmic,
+    // Krzysztof Narkowicz's fit of the ACES reference rendering transform
+    Aces
+}
+
+impl FromStr for ToneCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" | "none" => Ok(ToneCurve::Clamp),
+            "reinhard" => Ok(ToneCurve::Reinhard),
+            "filmic" | "hable" => Ok(ToneCurve::Filmic),
+            "aces" => Ok(ToneCurve::Aces),
+            other => Err(format!("Unknown tone curve `{}` (expected clamp, reinhard, filmic or aces)", other))
+        }
+    }
+}
+
+impl ToneCurve {
+    const FILMIC_WHITE: f64 = 11.2;
+
+    fn apply(&self, x: f64) -> f64 {
+        let x = x.max(0.0);
+
+        match self {
+            ToneCurve::Clamp => x,
+            ToneCurve::Reinhard => x / (1.0 + x),
+            ToneCurve::Filmic => ToneCurve::hable(x) / ToneCurve::hable(Self::FILMIC_WHITE),
+            ToneCurve::Aces => (x * (2.51 * x + 0.03)) / (x * (2.43 * x + 0.59) + 0.14)
+        }
+    }
+
+    fn hable(x: f64) -> f64 {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+}
+
+/// Turns rendered (linear, unbounded) colours into 8-bit sRGB pixels: exposure first, then the
+/// tone curve, then gamma encoding. Stills and video frames both go through this.
+#[derive(Debug, Copy, Clone)]
+pub struct ToneMapper {
+    pub curve: ToneCurve,
+    // in stops; +1 doubles the light, -1 halves it
+    pub exposure: f64
+}
+
+impl ToneMapper {
+    pub fn new(curve: ToneCurve, exposure: f64) -> Self {
+        Self {
+            curve,
+            exposure
+        }
+    }
+
+    pub fn map(&self, color: &Color) -> Rgba<u8> {
+        let scale = self.exposure.exp2();
+        color.map(|c| self.curve.apply(c * scale)).to_srgb()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::{tonemap::{ToneCurve, ToneMapper}, color::Color};
+
+    #[test]
+    fn curves() {
+        for curve in [ToneCurve::Reinhard, ToneCurve::Filmic, ToneCurve::Aces] {
+            let mapper = ToneMapper::new(curve, 0.0);
+
+            // black stays black, and brighter input never comes out darker
+            assert_eq!(mapper.map(&Color::BLACK).0, [0, 0, 0, 255]);
+
+            let levels: Vec<u8> = [0.1, 0.5, 1.0, 2.0, 8.0].iter().map(|&v| mapper.map(&(Color::WHITE * v)).0[0]).collect();
+            assert!(levels.windows(2).all(|w| w[0] <= w[1]), "{:?} {:?}", curve, levels);
+        }
+
+        // one stop of exposure is the same as twice the light
+        let brighter = ToneMapper::new(ToneCurve::Reinhard, 1.0);
+        assert_eq!(brighter.map(&(Color::WHITE * 0.3)), ToneMapper::new(ToneCurve::Reinhard, 0.0).map(&(Color::WHITE * 0.6)));
+
+        assert_eq!("ACES".parse::<ToneCurve>(), Ok(ToneCurve::Aces));
+        assert!("sepia".parse::<ToneCurve>().is_err());
+    }
+}
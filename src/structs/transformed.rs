@@ -0,0 +1,63 @@
+use super::{shape::Shape, matrix::Transform, ray::Ray, hit::Hit, bvh::Aabb, vec3::Vec3, appearance::Appearance};
+
+/// Wraps any shape with an affine transform. Rays are taken into the shape's own (object) space,
+/// intersected there, and the resulting hits are brought back out into world space.
+pub struct Transformed<'a> {
+    pub shape: Box<dyn Shape + Send + Sync + 'a>,
+    pub transform: Transform
+}
+
+impl<'a> Transformed<'a> {
+    pub fn new(shape: Box<dyn Shape + Send + Sync + 'a>, transform: Transform) -> Self {
+        Self {
+            shape,
+            transform
+        }
+    }
+}
+
+impl Shape for Transformed<'_> {
+    fn intersections(&self, ray: &Ray) -> Vec<Hit<'_>> {
+        let local = self.transform.ray_to_object(ray);
+
+        self.shape.intersections(&local).into_iter()
+            .map(|hit| hit_to_world(&self.transform, ray, hit))
+            .collect()
+    }
+
+    fn closest_hit(&self, ray: &Ray) -> Option<Hit<'_>> {
+        // affine maps keep the order of points along a line, so the closest hit stays the closest
+        let local = self.transform.ray_to_object(ray);
+
+        self.shape.closest_hit(&local).map(|hit| hit_to_world(&self.transform, ray, hit))
+    }
+
+    fn bounds(&self) -> Option<Aabb> {
+        self.shape.bounds().map(|bounds| self.transform.bounds_to_world(&bounds))
+    }
+
+    // in the wrapped shape's own space, before the transform
+    fn location_mut(&mut self) -> Option<&mut Vec3> {
+        self.shape.location_mut()
+    }
+
+    fn transform_mut(&mut self) -> Option<&mut Transform> {
+        Some(&mut self.transform)
+    }
+
+    fn appearance_mut(&mut self) -> Option<&mut Appearance> {
+        self.shape.appearance_mut()
+    }
+}
+
+/// Brings an object-space hit back into world space. The distance has to be recomputed,
+/// since the object-space ray was renormalised and no longer measures world units.
+pub fn hit_to_world<'h>(transform: &Transform, ray: &Ray, hit: Hit<'h>) -> Hit<'h> {
+    let point = transform.point_to_world(&hit.point);
+    let distance = (point - ray.origin).dot(&ray.direction);
+
+    Hit::new(ray, distance, transform.normal_to_world(&hit.normal), hit.appearance)
+        .with_shading_normal(transform.normal_to_world(&hit.shading_normal))
+        .with_uv(hit.uv.0, hit.uv.1)
+        .with_primitive(hit.primitive)
+}
@@ -1,4 +1,4 @@
-use std::ops::{Mul, Div, Add, Sub};
+use std::ops::{Mul, Div, Add, Sub, Index};
 
 // Yeah, I know this has been done a million times before, but this is an exercise.
 // TODO add cached length for speed increase?
@@ -62,6 +62,20 @@ impl PartialEq for Vec3 {
     }
 }
 
+// lets axis-generic code (bounding boxes, mostly) loop over 0..3 instead of spelling out x, y and z
+impl Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        match index {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 index out of range: {}", index)
+        }
+    }
+}
+
 // helper for converting 3-wide float slices to vectors
 impl<T> From<&[T; 3]> for Vec3
     where T: Into<f64> + Copy
@@ -139,17 +153,9 @@ impl Vec3 {
         self.x * self.x + self.y * self.y + self.z * self.z
     }
 
+    /// How far this reaches along `axis`, which should be a unit vector; for `Vec3::{I, J, K}` that's just x, y or z.
     pub fn component(&self, axis: &Self) -> f64 {
-        // Can't use a match here, since Vec3 cannot derive Eq
-        if *axis == Vec3::I {
-            self.x
-        } else if *axis == Vec3::J {
-            self.y
-        } else if *axis == Vec3::K {
-            self.z
-        } else {
-            panic!("component only accepts one of &Vec3::{{I, J, K}}");
-        }
+        self.dot(axis)
     }
 }
 
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt, path::{Path, PathBuf}, str::FromStr};
+
+use image::Rgba;
+use ndarray::Array3;
+use video_rs::{Encoder, EncoderSettings, Locator, Time};
+
+use super::error::{Error, Result};
+
+/// The codecs video_rs can encode to. It only drives libx264 (or FFmpeg's own H.264 encoder
+/// when that's missing), so there's just the one for now.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum VideoCodec {
+    H264
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "h264" | "h.264" | "avc" | "x264" => Ok(VideoCodec::H264),
+            other => Err(format!("Unsupported codec `{}` (only h264 is available)", other))
+        }
+    }
+}
+
+/// How the encoder stores colour: full-resolution brightness, with the chroma shared between
+/// 2x2 blocks (yuv420p, which plays everywhere), 2x1 pairs (yuv422p) or not at all (yuv444p).
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PixelFormat {
+    Yuv420,
+    Yuv422,
+    Yuv444
+}
+
+impl PixelFormat {
+    /// The width and height of the blocks sharing a chroma sample; frames have to be made of whole blocks.
+    pub fn block(&self) -> (usize, usize) {
+        match self {
+            PixelFormat::Yuv420 => (2, 2),
+            PixelFormat::Yuv422 => (2, 1),
+            PixelFormat::Yuv444 => (1, 1)
+        }
+    }
+
+    fn to_ffmpeg(self) -> video_rs::PixelFormat {
+        match self {
+            PixelFormat::Yuv420 => video_rs::PixelFormat::YUV420P,
+            PixelFormat::Yuv422 => video_rs::PixelFormat::YUV422P,
+            PixelFormat::Yuv444 => video_rs::PixelFormat::YUV444P
+        }
+    }
+}
+
+impl FromStr for PixelFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "yuv420p" => Ok(PixelFormat::Yuv420),
+            "yuv422p" => Ok(PixelFormat::Yuv422),
+            "yuv444p" => Ok(PixelFormat::Yuv444),
+            other => Err(format!("Unknown pixel format `{}` (expected yuv420p, yuv422p or yuv444p)", other))
+        }
+    }
+}
+
+impl fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PixelFormat::Yuv420 => write!(f, "yuv420p"),
+            PixelFormat::Yuv422 => write!(f, "yuv422p"),
+            PixelFormat::Yuv444 => write!(f, "yuv444p")
+        }
+    }
+}
+
+/// What the encoder aims for: a steady quality, letting the file be as large as it needs to be,
+/// or a steady size, letting the quality go where it has to.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RateControl {
+    // the constant rate factor, from 0 (lossless) to 51 (worst); each 6 roughly halves the size
+    Crf(u8),
+    // average bits per second
+    Bitrate(u64)
+}
+
+impl RateControl {
+    pub const MAX_CRF: u8 = 51;
+    // x264's own default
+    pub const DEFAULT: RateControl = RateControl::Crf(23);
+}
+
+/// Reads a bitrate in bits per second, with an optional `k` or `M` suffix: `800k`, `2.5M`, `4000000`.
+pub fn parse_bitrate(s: &str) -> std::result::Result<u64, String> {
+    let error = || format!("Invalid bitrate `{}` (expected bits per second, like 800k or 4M)", s);
+
+    let (number, scale) = match s.trim().char_indices().last() {
+        Some((i, 'k' | 'K')) => (&s.trim()[..i], 1e3),
+        Some((i, 'm' | 'M')) => (&s.trim()[..i], 1e6),
+        _ => (s.trim(), 1.0)
+    };
+
+    let bitrate = number.parse::<f64>().map_err(|_| error())? * scale;
+
+    if bitrate.is_finite() && bitrate >= 1.0 {
+        Ok(bitrate.round() as u64)
+    } else {
+        Err(error())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VideoOptions {
+    pub codec: VideoCodec,
+    pub pixel_format: PixelFormat,
+    pub rate: RateControl,
+    // the most frames between keyframes; None leaves it to the encoder (250 for x264)
+    pub keyframe_interval: Option<usize>,
+    pub frame_rate: usize
+}
+
+impl VideoOptions {
+    /// H.264 in yuv420p at the encoder's default quality, which is what most players expect.
+    pub fn new(frame_rate: usize) -> Self {
+        Self {
+            codec: VideoCodec::H264,
+            pixel_format: PixelFormat::Yuv420,
+            rate: RateControl::DEFAULT,
+            keyframe_interval: None,
+            frame_rate
+        }
+    }
+
+    // The encoder's own option names, as the `ffmpeg` command line would take them
+    fn encoder_options(&self) -> HashMap<String, String> {
+        let mut options = HashMap::from([("preset".to_string(), "medium".to_string())]);
+
+        match self.rate {
+            RateControl::Crf(crf) => options.insert("crf".into(), crf.to_string()),
+            RateControl::Bitrate(bitrate) => options.insert("b".into(), bitrate.to_string())
+        };
+
+        if let Some(interval) = self.keyframe_interval {
+            options.insert("g".into(), interval.to_string());
+        }
+
+        options
+    }
+}
+
+/// Encodes frames into a video file through FFmpeg, one at a time. The container is picked from
+/// the file extension (.mp4, .mkv, .mov and so on).
+pub struct VideoWriter {
+    path: PathBuf,
+    encoder: Encoder,
+    position: Time,
+    frame_time: Time,
+    width: usize,
+    height: usize
+}
+
+impl VideoWriter {
+    pub fn create(path: &Path, width: usize, height: usize, options: VideoOptions) -> Result<Self> {
+        let output_error = |message| Error::Output { path: path.to_path_buf(), message };
+        let (block_width, block_height) = options.pixel_format.block();
+
+        if !(width.is_multiple_of(block_width) && height.is_multiple_of(block_height)) {
+            return Err(output_error(format!("{} needs dimensions in multiples of {}x{} (got {}x{})",
+                options.pixel_format, block_width, block_height, width, height)));
+        }
+
+        if let RateControl::Crf(crf) = options.rate {
+            if crf > RateControl::MAX_CRF {
+                return Err(output_error(format!("CRF must be between 0 and {} (got {})", RateControl::MAX_CRF, crf)));
+            }
+        }
+
+        if options.keyframe_interval == Some(0) {
+            return Err(output_error("the keyframe interval must be at least 1".into()));
+        }
+
+        // init's errors come boxed, rather than as a `video_rs::Error`
+        video_rs::init().map_err(|err: Box<dyn std::error::Error>| output_error(err.to_string()))?;
+
+        let video_error = |source| Error::Video { path: path.to_path_buf(), source };
+
+        let destination: Locator = path.to_path_buf().into();
+        let settings = match options.codec {
+            VideoCodec::H264 => EncoderSettings::for_h264_custom(width, height, options.pixel_format.to_ffmpeg(), options.encoder_options().into())
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            encoder: Encoder::new(&destination, settings).map_err(video_error)?,
+            position: Time::zero(),
+            frame_time: Time::from_nth_of_a_second(options.frame_rate),
+            width,
+            height
+        })
+    }
+
+    pub fn add_frame(&mut self, pixels: &[Rgba<u8>]) -> Result<()> {
+        let frame = Array3::from_shape_fn((self.height, self.width, 3), |(y, x, c)| pixels[self.width * y + x].0[c]);
+        self.encoder.encode(&frame, &self.position).map_err(|source| Error::Video { path: self.path.clone(), source })?;
+
+        self.position = self.position.aligned_with(&self.frame_time).add();
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> Result<()> {
+        self.encoder.finish().map_err(|source| Error::Video { path: self.path.clone(), source })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::structs::video_output::{parse_bitrate, PixelFormat, RateControl, VideoOptions};
+
+    #[test]
+    fn encoder_options() {
+        assert_eq!(parse_bitrate("800k"), Ok(800_000));
+        assert_eq!(parse_bitrate("2.5M"), Ok(2_500_000));
+        assert_eq!(parse_bitrate("4000000"), Ok(4_000_000));
+        assert!(parse_bitrate("fast").is_err() && parse_bitrate("0k").is_err() && parse_bitrate("").is_err());
+
+        assert_eq!("YUV444P".parse(), Ok(PixelFormat::Yuv444));
+        assert!("rgb24".parse::<PixelFormat>().is_err());
+
+        let options = VideoOptions { rate: RateControl::Bitrate(800_000), keyframe_interval: Some(30), ..VideoOptions::new(30) };
+        let dictionary = options.encoder_options();
+        assert_eq!((dictionary["b"].as_str(), dictionary["g"].as_str()), ("800000", "30"));
+        assert!(!dictionary.contains_key("crf"));
+
+        assert_eq!(VideoOptions::new(30).encoder_options()["crf"], "23");
+    }
+}